@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use xiv::tex::{Image, TexAttributes, TexFormat};
+
+const SIZE: u16 = 4096;
+
+/// 4K BC7 texture made of mode 6 blocks with pseudo-random endpoints and
+/// indices.
+fn bc7_texture() -> Image {
+    let blocks = (SIZE as usize / 4).pow(2);
+    let mut state = 0x2545_f491_u32;
+    let mut data = Vec::with_capacity(blocks * 16);
+    for _ in 0..blocks {
+        let mut block = [0x40; 16];
+        for byte in &mut block[1..] {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *byte = state as u8;
+        }
+        data.extend(block);
+    }
+    Image::new(
+        TexAttributes::TEXTURE_TYPE_2D,
+        TexFormat::Bc7,
+        SIZE,
+        SIZE,
+        1,
+        1,
+        vec![data.into()].into(),
+    )
+}
+
+fn decode_bc7(c: &mut Criterion) {
+    let image = bc7_texture();
+    let mut group = c.benchmark_group("tex");
+    group.sample_size(20);
+    group.bench_function("decode 4096x4096 bc7", |b| {
+        b.iter(|| image.export().unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode_bc7);
+criterion_main!(benches);
@@ -1,4 +1,7 @@
-use crate::{error::XivError, tex::Image};
+use crate::{
+    error::XivError,
+    tex::{Image, TexHeader},
+};
 use binrw::{binread, BinRead};
 use flate2::read::DeflateDecoder;
 use std::{
@@ -138,21 +141,10 @@ fn read_image_file(mut input: impl Read + Seek) -> Result<Image, XivError> {
         block_count: u32,
     }
 
-    #[binread]
-    #[br(little)]
-    struct ImageHeader {
-        _unk0: u32,
-        format: u32,
-        width: u16,
-        height: u16,
-        layers: u16,
-        count: u16,
-    }
-
     let offset = input.stream_position().map_err(XivError::DatSeek)?;
     let header = FileHeader::read(&mut input).map_err(XivError::DatFileHeader)?;
     input.seek(SeekFrom::Start(offset + header.len as u64)).map_err(XivError::DatSeek)?;
-    let image = ImageHeader::read(&mut input).map_err(XivError::DatFileHeader)?;
+    let tex_header = TexHeader::read(&mut input).map_err(XivError::TexHeader)?;
 
     let mut mipmaps = Vec::with_capacity(header.mipmaps.len());
     for mipmap in header.mipmaps {
@@ -166,14 +158,7 @@ fn read_image_file(mut input: impl Read + Seek) -> Result<Image, XivError> {
         mipmaps.push(data.into_inner().into_boxed_slice());
     }
 
-    Ok(Image {
-        format: image.format,
-        width: image.width,
-        height: image.height,
-        layers: image.layers,
-        count: image.count,
-        mipmaps: mipmaps.into_boxed_slice(),
-    })
+    Ok(Image::from_header(&tex_header, mipmaps.into_boxed_slice()))
 }
 
 pub struct InnerFilePtr {
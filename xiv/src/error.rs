@@ -1,3 +1,4 @@
+use crate::ex::ExdLocation;
 use std::io;
 use thiserror::Error;
 
@@ -30,27 +31,66 @@ pub enum XivError {
     #[error("Failed to decode .dat inner file block")]
     DatBlockDecoding(#[source] io::Error),
 
+    #[error("Unknown locale code \"{0}\"")]
+    LocaleCode(Box<str>),
+
+    #[error("Malformed SeString payload at byte {0}")]
+    SeString(usize),
+    #[error("Malformed SeString markup at character {0}")]
+    SeStringMarkup(usize),
+    #[error("SeString sheet macros nest too deep at sheet {0} row {1}")]
+    SeStringDepth(Box<str>, u32),
+
     #[error("Failed to read .exh file")]
     Exh(#[source] binrw::Error),
     #[error("Unable to find {0}")]
     ExhNotFound(Box<str>),
+    #[error("Malformed column layout \"{0}\"")]
+    ExhLayout(Box<str>),
+    #[error("Column layout of sheet {0} has changed: {}", crate::ex::layout::join_diffs(.1))]
+    ExhLayoutMismatch(Box<str>, Vec<crate::ex::layout::ColumnDiff>),
     #[error("Unable to find {0}")]
     ExdNotFound(Box<str>),
-    #[error("Failed to seek within .exd file")]
-    ExdSeek(#[source] io::Error),
-    #[error("Failed to read .exd file header")]
-    ExdFileHeader(#[source] binrw::Error),
-    #[error("Failed to read .exd row header")]
-    ExdRowHeader(#[source] binrw::Error),
-    #[error("Failed to read .exd subrow header")]
-    ExdSubRowHeader(#[source] binrw::Error),
-    #[error("Failed to deserialize .exd row ({0})")]
-    ExdDeserialization(Box<str>),
+    #[error("Failed to read .exd file at {0}")]
+    ExdFile(Box<ExdLocation>, #[source] Box<XivError>),
+    #[error("Failed to seek within .exd file at {0}")]
+    ExdSeek(Box<ExdLocation>, #[source] io::Error),
+    #[error("Failed to read .exd file header at {0}")]
+    ExdFileHeader(Box<ExdLocation>, #[source] binrw::Error),
+    #[error("Failed to read .exd row header at {0}")]
+    ExdRowHeader(Box<ExdLocation>, #[source] binrw::Error),
+    #[error("Failed to read .exd subrow header at {0}")]
+    ExdSubRowHeader(Box<ExdLocation>, #[source] binrw::Error),
+    #[error("Sheet {0} has no {} locale", .1.code())]
+    ExdLocaleNotFound(Box<str>, crate::ex::Locale),
+    #[error("Failed to deserialize .exd row at {0} ({1})")]
+    ExdDeserialization(Box<ExdLocation>, Box<str>),
+    #[error("Unable to parse \"{1}\" as {0}")]
+    ValueParse(crate::ex::ValueType, Box<str>),
+    #[error("Unable to convert {0} value to {1}")]
+    ValueConversion(&'static str, &'static str),
+    #[error("Failed to serialize .exd row ({0})")]
+    ExdSerialization(Box<str>),
+
+    #[error("Malformed query at character {0}")]
+    QuerySyntax(usize),
+    #[error("Unknown column \"{0}\"")]
+    QueryColumn(Box<str>),
+    #[error("Column \"{0}\" does not hold row ids")]
+    QueryLink(Box<str>),
 
     #[error("Unable to export an image with format={0}, which is not implemented yet")]
     TexFormat(u32),
     #[error("Image's pixel data is invalid or corrupted")]
     TexData,
+    #[error("Unable to encode an image of {0}x{1} pixels into .tex")]
+    TexSize(u32, u32),
+    #[error("Failed to read .tex header")]
+    TexHeader(#[source] binrw::Error),
+    #[error("Failed to read .dds header")]
+    DdsHeader(#[source] binrw::Error),
+    #[error("Unsupported .dds pixel format ({0})")]
+    DdsFormat(Box<str>),
 
     #[error(transparent)]
     IO(io::Error),
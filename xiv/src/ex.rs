@@ -1,16 +1,30 @@
-use crate::{dat::InnerFilePtr, error::XivError, sqpack::SqPack};
+use crate::{dat::InnerFilePtr, error::XivError, sestring::SeString, sqpack::SqPack};
 use binrw::{binread, BinRead};
-use serde::{de, forward_to_deserialize_any, Deserialize, Serialize};
+use schema::SheetSchema;
+use serde::{
+    de::{self, IntoDeserializer},
+    forward_to_deserialize_any, Deserialize, Serialize,
+};
 use std::{
+    borrow::Cow,
     fmt,
     io::{Cursor, Seek, SeekFrom},
     iter::FusedIterator,
     marker::PhantomData,
     rc::Rc,
+    str::FromStr,
     sync::Arc,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub mod info;
+pub mod layout;
+pub mod query;
+pub mod row_ref;
+pub mod schema;
+pub mod write;
+pub mod zero_as_none;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[binread]
 #[br(little, repr = u16)]
 pub enum Locale {
@@ -37,6 +51,11 @@ impl Locale {
             Self::Korean => "_ko",
         }
     }
+
+    /// Code which `FromStr` parses back, e.g. "en", or "none".
+    pub fn code(&self) -> &'static str {
+        self.suffix().strip_prefix('_').unwrap_or("none")
+    }
 }
 
 impl std::fmt::Display for Locale {
@@ -45,6 +64,26 @@ impl std::fmt::Display for Locale {
     }
 }
 
+impl FromStr for Locale {
+    type Err = XivError;
+
+    /// Parses locale code as used in .exd file names (e.g. "en" or "_en").
+    /// Empty string and "none" map to `Locale::None`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('_').to_lowercase().as_str() {
+            "" | "none" => Ok(Self::None),
+            "ja" => Ok(Self::Japanese),
+            "en" => Ok(Self::English),
+            "de" => Ok(Self::German),
+            "fr" => Ok(Self::French),
+            "chs" => Ok(Self::ChineseSimplified),
+            "cht" => Ok(Self::ChineseTraditional),
+            "ko" => Ok(Self::Korean),
+            _ => Err(XivError::LocaleCode(s.into())),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[binread]
 #[br(big, repr = u16)]
@@ -96,13 +135,38 @@ impl ValueType {
     }
 }
 
+impl ValueType {
+    /// Bit number within a byte for packed bool columns.
+    pub fn packed_bit(self) -> Option<u8> {
+        match self {
+            Self::PackedBool0 => Some(0),
+            Self::PackedBool1 => Some(1),
+            Self::PackedBool2 => Some(2),
+            Self::PackedBool3 => Some(3),
+            Self::PackedBool4 => Some(4),
+            Self::PackedBool5 => Some(5),
+            Self::PackedBool6 => Some(6),
+            Self::PackedBool7 => Some(7),
+            _ => None,
+        }
+    }
+
+    pub fn packed_bit_mask(self) -> Option<u8> {
+        self.packed_bit().map(|bit| 1 << bit)
+    }
+}
+
 impl std::fmt::Display for ValueType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(self.type_tag())
     }
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+/// Value of a row key or column.
+///
+/// Values are equal only if they have the same variant, see `cmp_loose` for
+/// comparing them by value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum Value {
     Bool(bool),
@@ -116,6 +180,7 @@ pub enum Value {
     UInt64(u64),
     Float(f32),
     String(Box<str>),
+    SeString(SeString),
 }
 
 impl Value {
@@ -131,11 +196,275 @@ impl Value {
             Self::Int64(_) => "i64",
             Self::UInt64(_) => "u64",
             Self::Float(_) => "f32",
-            Self::String(_) => "str",
+            Self::String(_) | Self::SeString(_) => "str",
+        }
+    }
+
+    /// Parses value of a column from its text form, as written by CSV export.
+    ///
+    /// Strings are read as SeString markup, and as plain text if it has no
+    /// macros. `<` and `\` of plain text are escaped with `\`, the way
+    /// `to_markup` writes them.
+    pub fn parse(vtype: ValueType, text: &str) -> Result<Self, XivError> {
+        fn num<T: FromStr>(vtype: ValueType, text: &str) -> Result<T, XivError> {
+            text.trim()
+                .parse()
+                .map_err(|_| XivError::ValueParse(vtype, text.into()))
+        }
+
+        Ok(match vtype {
+            ValueType::String => {
+                let s = SeString::from_markup(text)?;
+                match s.is_plain() {
+                    true => Self::String(s.to_plain_text().into()),
+                    false => Self::SeString(s),
+                }
+            }
+            ValueType::Int8 => Self::Int8(num(vtype, text)?),
+            ValueType::UInt8 => Self::UInt8(num(vtype, text)?),
+            ValueType::Int16 => Self::Int16(num(vtype, text)?),
+            ValueType::UInt16 => Self::UInt16(num(vtype, text)?),
+            ValueType::Int32 => Self::Int32(num(vtype, text)?),
+            ValueType::UInt32 => Self::UInt32(num(vtype, text)?),
+            ValueType::Int64 => Self::Int64(num(vtype, text)?),
+            ValueType::UInt64 => Self::UInt64(num(vtype, text)?),
+            ValueType::Float32 => Self::Float(num(vtype, text)?),
+            _ => match text.trim().to_lowercase().as_str() {
+                "true" | "1" => Self::Bool(true),
+                "false" | "0" => Self::Bool(false),
+                _ => return Err(XivError::ValueParse(vtype, text.into())),
+            },
+        })
+    }
+
+    /// Converts value to a column type, checking that it fits.
+    pub fn convert(&self, vtype: ValueType) -> Result<Self, XivError> {
+        Ok(match vtype {
+            ValueType::String => match self {
+                Self::String(_) | Self::SeString(_) => self.clone(),
+                _ => return Err(XivError::ValueConversion(self.type_tag(), vtype.type_tag())),
+            },
+            ValueType::Int8 => Self::Int8(self.try_into()?),
+            ValueType::UInt8 => Self::UInt8(self.try_into()?),
+            ValueType::Int16 => Self::Int16(self.try_into()?),
+            ValueType::UInt16 => Self::UInt16(self.try_into()?),
+            ValueType::Int32 => Self::Int32(self.try_into()?),
+            ValueType::UInt32 => Self::UInt32(self.try_into()?),
+            ValueType::Int64 => Self::Int64(self.try_into()?),
+            ValueType::UInt64 => Self::UInt64(self.try_into()?),
+            ValueType::Float32 => Self::Float(self.try_into()?),
+            _ => Self::Bool(self.try_into()?),
+        })
+    }
+
+    fn number(&self) -> Option<Number> {
+        Some(match *self {
+            Self::Int8(v) => Number::Int(v.into()),
+            Self::Int16(v) => Number::Int(v.into()),
+            Self::Int32(v) => Number::Int(v.into()),
+            Self::Int64(v) => Number::Int(v.into()),
+            Self::UInt8(v) => Number::Int(v.into()),
+            Self::UInt16(v) => Number::Int(v.into()),
+            Self::UInt32(v) => Number::Int(v.into()),
+            Self::UInt64(v) => Number::Int(v.into()),
+            Self::Float(v) => Number::Float(v.into()),
+            _ => return None,
+        })
+    }
+
+    /// Text of strings without any macros.
+    fn text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Self::String(v) => Some(Cow::Borrowed(v)),
+            Self::SeString(v) => Some(Cow::Owned(v.to_plain_text())),
+            _ => None,
+        }
+    }
+
+    fn as_i128(&self) -> Option<i128> {
+        match self.number()? {
+            Number::Int(v) => Some(v),
+            Number::Float(_) => None,
+        }
+    }
+
+    /// Value of any integer variant, if it fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_i128()?.try_into().ok()
+    }
+
+    /// Value of any integer variant, if it fits.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i128()?.try_into().ok()
+    }
+
+    /// Value of any integer variant, if it fits (e.g. a row id).
+    pub fn as_u32(&self) -> Option<u32> {
+        self.as_i128()?.try_into().ok()
+    }
+
+    /// Value of float variant, or of integer variants which convert exactly.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.number()? {
+            Number::Float(v) => Some(v),
+            Number::Int(v) => Some(v as f64).filter(|f| *f as i128 == v),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Text of plain strings, strings with macros are kept as `SeString`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_sestring(&self) -> Option<&SeString> {
+        match self {
+            Self::SeString(v) => Some(v),
+            _ => None,
         }
     }
 }
 
+/// Text form read back by `Value::parse`, with strings as SeString markup.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(v) => v.fmt(f),
+            Self::Int8(v) => v.fmt(f),
+            Self::Int16(v) => v.fmt(f),
+            Self::Int32(v) => v.fmt(f),
+            Self::Int64(v) => v.fmt(f),
+            Self::UInt8(v) => v.fmt(f),
+            Self::UInt16(v) => v.fmt(f),
+            Self::UInt32(v) => v.fmt(f),
+            Self::UInt64(v) => v.fmt(f),
+            Self::Float(v) => v.fmt(f),
+            Self::String(v) => v.fmt(f),
+            Self::SeString(v) => v.fmt(f),
+        }
+    }
+}
+
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl Value {
+    /// Text form which `parse` reads back. Unlike `Display`, `<` and `\` of
+    /// plain strings are escaped so they are not taken for markup.
+    pub fn to_markup(&self) -> String {
+        match self {
+            Self::String(v) => SeString::from(&**v).to_markup(),
+            Self::SeString(v) => v.to_markup(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Compares numbers by value regardless of their type and strings by
+    /// their text. Values of other kinds, such as a number and a string, are
+    /// not comparable.
+    pub fn cmp_loose(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a.partial_cmp(b),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::String(_) | Self::SeString(_), Self::String(_) | Self::SeString(_)) => {
+                self.text().partial_cmp(&other.text())
+            }
+            _ => match (self.number()?, other.number()?) {
+                (Number::Int(a), Number::Int(b)) => a.partial_cmp(&b),
+                (Number::Int(a), Number::Float(b)) => (a as f64).partial_cmp(&b),
+                (Number::Float(a), Number::Int(b)) => a.partial_cmp(&(b as f64)),
+                (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+            },
+        }
+    }
+}
+
+macro_rules! value_conversions {
+    ($($variant:ident($ty:ty) => $get:expr,)*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(v: $ty) -> Self {
+                    Self::$variant(v.into())
+                }
+            }
+
+            impl TryFrom<&Value> for $ty {
+                type Error = XivError;
+
+                fn try_from(value: &Value) -> Result<Self, Self::Error> {
+                    let get: fn(&Value) -> Option<$ty> = $get;
+                    get(value).ok_or_else(|| {
+                        XivError::ValueConversion(value.type_tag(), stringify!($ty))
+                    })
+                }
+            }
+
+            impl TryFrom<Value> for $ty {
+                type Error = XivError;
+
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    Self::try_from(&value)
+                }
+            }
+        )*
+    };
+}
+
+value_conversions! {
+    Bool(bool) => Value::as_bool,
+    Int8(i8) => |v| v.as_i128()?.try_into().ok(),
+    Int16(i16) => |v| v.as_i128()?.try_into().ok(),
+    Int32(i32) => |v| v.as_i128()?.try_into().ok(),
+    Int64(i64) => Value::as_i64,
+    UInt8(u8) => |v| v.as_i128()?.try_into().ok(),
+    UInt16(u16) => |v| v.as_i128()?.try_into().ok(),
+    UInt32(u32) => Value::as_u32,
+    UInt64(u64) => Value::as_u64,
+    Float(f32) => |v| match v {
+        Value::Float(f) => Some(*f),
+        _ => v.as_f64().map(|f| f as f32).filter(|f| v.as_f64() == Some(f64::from(*f))),
+    },
+    String(String) => |v| match v {
+        Value::String(s) => Some(s.to_string()),
+        Value::SeString(s) => Some(s.to_markup()),
+        _ => None,
+    },
+}
+
+impl From<SeString> for Value {
+    fn from(v: SeString) -> Self {
+        Self::SeString(v)
+    }
+}
+
+/// Deserializes value of a column type from a value of any type convertible
+/// to it, or from its text form (see `Value::parse`), e.g. a CSV cell.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueSeed(pub ValueType);
+
+impl<'de> de::DeserializeSeed<'de> for ValueSeed {
+    type Value = Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        let value = match Value::deserialize(deserializer)? {
+            Value::String(text) => Value::parse(self.0, &text),
+            value => value.convert(self.0),
+        };
+        value.map_err(de::Error::custom)
+    }
+}
+
 struct ValueVisitor;
 
 impl<'de> de::Visitor<'de> for ValueVisitor {
@@ -175,9 +504,20 @@ impl<'de> de::Visitor<'de> for ValueVisitor {
     fn visit_f32<E: de::Error>(self, v: f32) -> Result<Self::Value, E> {
         Ok(Value::Float(v))
     }
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Value::String(v.into()))
+    }
     fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
         Ok(Value::String(v.into()))
     }
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        let s = SeString::parse(v).map_err(E::custom)?;
+        if s.is_plain() {
+            Ok(Value::String(s.to_plain_text().into()))
+        } else {
+            Ok(Value::SeString(s))
+        }
+    }
 }
 
 impl<'de> de::Deserialize<'de> for Value {
@@ -191,7 +531,35 @@ impl<'de> de::Deserialize<'de> for Value {
 
 pub type Row = Vec<Value>;
 
-#[derive(Debug)]
+/// Row value of a sheet read in all of its locales at once.
+#[derive(Debug, PartialEq)]
+pub enum LocalizedValue {
+    /// Value which is the same for every locale (row ids and non-string columns).
+    Shared(Value),
+    /// Value of a string column for each locale, in `Exh::languages` order.
+    PerLocale(Box<[(Locale, Value)]>),
+}
+
+impl LocalizedValue {
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        let (shared, per_locale) = match self {
+            Self::Shared(value) => (Some(value), &[][..]),
+            Self::PerLocale(values) => (None, &values[..]),
+        };
+        shared.into_iter().chain(per_locale.iter().map(|(_, v)| v))
+    }
+
+    pub fn get(&self, locale: Locale) -> Option<&Value> {
+        match self {
+            Self::Shared(value) => Some(value),
+            Self::PerLocale(values) => values.iter().find(|(l, _)| *l == locale).map(|(_, v)| v),
+        }
+    }
+}
+
+pub type LocalizedRow = Vec<LocalizedValue>;
+
+#[derive(Debug, Clone)]
 #[binread]
 #[br(big, magic = b"EXHF")]
 pub struct Exh {
@@ -207,15 +575,87 @@ pub struct Exh {
     pub row_count: u32,
     pub unk3: u32,
     pub unk4: u32,
-    #[br(count = column_count)]
+    #[br(count = usize::from(column_count))]
     pub columns: Vec<ExColumn>,
-    #[br(count = page_count)]
+    #[br(count = usize::from(page_count))]
     pub pages: Vec<ExPage>,
-    #[br(count = language_count)]
+    #[br(count = usize::from(language_count))]
     pub languages: Vec<Locale>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Exh {
+    /// Groups packed bool columns sharing the same byte into bitfields,
+    /// ordered by their offset within a row.
+    pub fn bitfields(&self) -> Vec<ExBitfield> {
+        let mut bitfields: Vec<ExBitfield> = Vec::new();
+        for (idx, column) in self.columns.iter().enumerate() {
+            if let Some(bit) = column.vtype.packed_bit() {
+                let pos = match bitfields.binary_search_by_key(&column.offset, |b| b.offset) {
+                    Ok(pos) => pos,
+                    Err(pos) => {
+                        let bitfield = ExBitfield {
+                            offset: column.offset,
+                            columns: [None; 8],
+                        };
+                        bitfields.insert(pos, bitfield);
+                        pos
+                    }
+                };
+                bitfields[pos].columns[bit as usize] = Some(idx);
+            }
+        }
+        bitfields
+    }
+
+    /// Columns as they are read with `ExdReadOptions::bitfields`: packed bool
+    /// columns sharing a byte are merged into a single bitfield, placed where
+    /// the first of them is.
+    pub fn fields(&self) -> Vec<ExField> {
+        let bitfields = self.bitfields();
+        let mut fields = Vec::with_capacity(self.columns.len());
+        for (idx, column) in self.columns.iter().enumerate() {
+            if column.vtype.packed_bit().is_none() {
+                fields.push(ExField::Column(idx));
+            } else if let Some(bitfield) = bitfields
+                .iter()
+                .find(|b| b.offset == column.offset && b.first_column() == Some(idx))
+            {
+                fields.push(ExField::Bitfield(bitfield.clone()));
+            }
+        }
+        fields
+    }
+
+    /// Names of row keys (`id`, `subid`) followed by names of columns from
+    /// `schema` or `col_N`.
+    pub fn column_names(&self, schema: Option<&SheetSchema>) -> Vec<Box<str>> {
+        let mut names: Vec<Box<str>> = vec!["id".into()];
+        if self.variant == ExVariant::SubRows {
+            names.push("subid".into());
+        }
+        names.extend((0..self.columns.len()).map(|idx| match schema {
+            Some(schema) => schema.column_name(idx).into(),
+            None => format!("col_{idx}").into(),
+        }));
+        names
+    }
+
+    /// Picks locale of .exd pages to read for the requested one.
+    ///
+    /// Sheets without any localized columns only have `Locale::None` pages,
+    /// which are used for any requested locale.
+    pub fn resolve_locale(&self, locale: Locale) -> Option<Locale> {
+        if self.languages.contains(&locale) {
+            Some(locale)
+        } else if self.languages.iter().all(|l| *l == Locale::None) {
+            Some(Locale::None)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[binread]
 #[br(big, repr = u8)]
 pub enum ExVariant {
@@ -223,7 +663,7 @@ pub enum ExVariant {
     SubRows = 2,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[binread]
 #[br(big)]
 pub struct ExColumn {
@@ -231,7 +671,56 @@ pub struct ExColumn {
     pub offset: u16,
 }
 
-#[derive(Debug)]
+/// Byte of a row holding up to 8 packed bool columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExBitfield {
+    pub offset: u16,
+    /// Index within `Exh::columns` of the column stored in each bit.
+    pub columns: [Option<usize>; 8],
+}
+
+impl ExBitfield {
+    /// Mask of bits which are used by columns.
+    pub fn mask(&self) -> u8 {
+        (0..8)
+            .filter(|bit| self.columns[*bit].is_some())
+            .fold(0, |m, bit| m | 1 << bit)
+    }
+
+    /// Lowest index of its columns.
+    pub fn first_column(&self) -> Option<usize> {
+        self.columns.iter().flatten().min().copied()
+    }
+}
+
+/// Value of a row as it is read and written: a single column, or a whole
+/// bitfield as `u8` with unused bits cleared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExField {
+    Column(usize),
+    Bitfield(ExBitfield),
+}
+
+impl ExField {
+    /// Index within `Exh::columns` of the column, or of the first column of
+    /// the bitfield.
+    pub fn column(&self) -> usize {
+        match self {
+            Self::Column(idx) => *idx,
+            Self::Bitfield(bitfield) => bitfield.first_column().expect("bitfield without columns"),
+        }
+    }
+
+    /// Mask of used bits if this is a bitfield.
+    pub fn bitfield_mask(&self) -> Option<u8> {
+        match self {
+            Self::Column(_) => None,
+            Self::Bitfield(bitfield) => Some(bitfield.mask()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 #[binread]
 #[br(big)]
 pub struct ExPage {
@@ -261,6 +750,51 @@ struct ExdRowPtr {
     pub offset: u32,
 }
 
+/// Place within a sheet where reading has failed. Parts which are not known
+/// at the point of failure are left as `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExdLocation {
+    pub sheet: Option<Box<str>>,
+    pub locale: Option<Locale>,
+    /// Path of .exd page file within SqPack repository.
+    pub path: Option<Box<str>>,
+    pub row_id: Option<u32>,
+    pub subrow_id: Option<u16>,
+    /// Index within `Exh::columns`.
+    pub column: Option<usize>,
+    pub vtype: Option<ValueType>,
+}
+
+impl fmt::Display for ExdLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(sheet) = &self.sheet {
+            parts.push(format!("sheet {sheet}"));
+        }
+        if let Some(locale) = self.locale {
+            parts.push(format!("locale {}", locale.code()));
+        }
+        if let Some(path) = &self.path {
+            parts.push(path.to_string());
+        }
+        if let Some(row_id) = self.row_id {
+            parts.push(format!("row {row_id}"));
+        }
+        if let Some(subrow_id) = self.subrow_id {
+            parts.push(format!("subrow {subrow_id}"));
+        }
+        match (self.column, self.vtype) {
+            (Some(column), Some(vtype)) => parts.push(format!("column {column} ({vtype})")),
+            (Some(column), None) => parts.push(format!("column {column}")),
+            _ => {}
+        }
+        match parts.is_empty() {
+            true => f.write_str("unknown location"),
+            false => f.write_str(&parts.join(", ")),
+        }
+    }
+}
+
 struct ExdRowReader {
     exh: Rc<Exh>,
     exd_data: Rc<[u8]>,
@@ -268,12 +802,102 @@ struct ExdRowReader {
     id_expected: bool,
     subid: u16,
     subid_expected: bool,
+    has_subid: bool,
     offset: u64,
+    /// Columns to read, indexed by `column_idx`.
+    fields: Rc<[ExField]>,
     column_idx: usize,
+    /// Names of keys and columns to present top-level row as a map with.
+    names: Option<Rc<[Box<str>]>>,
+    /// Index of the last column read, to report errors at.
+    last_column: Option<usize>,
 }
 
 impl ExdRowReader {
-    pub fn new(exh: Rc<Exh>, exd_data: Rc<[u8]>, id: u32, subid: Option<u16>, offset: u64) -> Self {
+    /// Index within `Exh::columns` of the next column, and mask of its bits
+    /// if it is read as a whole bitfield.
+    fn next_column(&self) -> Option<(usize, ExColumn, Option<u8>)> {
+        let field = self.fields.get(self.column_idx)?;
+        let idx = field.column();
+        Some((idx, self.exh.columns[idx], field.bitfield_mask()))
+    }
+
+    /// Reads raw SeString bytes of the next column if it is a string one.
+    fn next_string(&mut self) -> Result<Option<Vec<u8>>, ExdDeserializerError> {
+        if self.id_expected || self.subid_expected {
+            return Ok(None);
+        }
+        let (idx, column) = match self.next_column() {
+            Some((idx, column, None)) if column.vtype == ValueType::String => (idx, column),
+            _ => return Ok(None),
+        };
+
+        let mut cursor = Cursor::new(&self.exd_data);
+        cursor.seek(SeekFrom::Start(self.offset + column.offset as u64))?;
+        let str_offset = u32::read_be(&mut cursor)?;
+        let abs_offset = self.offset + self.exh.data_offset as u64 + str_offset as u64;
+        cursor.seek(SeekFrom::Start(abs_offset))?;
+        let raw = binrw::NullString::read(&mut cursor)?.0;
+
+        self.last_column = Some(idx);
+        self.column_idx += 1;
+        Ok(Some(raw))
+    }
+
+    /// Reads the next key or integer column without consuming it.
+    fn peek_integer(&mut self) -> Result<Option<i128>, ExdDeserializerError> {
+        if self.id_expected {
+            return Ok(Some(self.id.into()));
+        } else if self.subid_expected {
+            return Ok(Some(self.subid.into()));
+        }
+        let Some((idx, column, mask)) = self.next_column() else {
+            return Ok(None);
+        };
+        self.last_column = Some(idx);
+
+        let mut cursor = Cursor::new(&self.exd_data);
+        cursor.seek(SeekFrom::Start(self.offset + column.offset as u64))?;
+        if let Some(mask) = mask {
+            return Ok(Some((u8::read_be(&mut cursor)? & mask).into()));
+        }
+        let value = match column.vtype {
+            ValueType::Int8 => i8::read_be(&mut cursor)?.into(),
+            ValueType::Int16 => i16::read_be(&mut cursor)?.into(),
+            ValueType::Int32 => i32::read_be(&mut cursor)?.into(),
+            ValueType::Int64 => i64::read_be(&mut cursor)?.into(),
+            ValueType::UInt8 => u8::read_be(&mut cursor)?.into(),
+            ValueType::UInt16 => u16::read_be(&mut cursor)?.into(),
+            ValueType::UInt32 => u32::read_be(&mut cursor)?.into(),
+            ValueType::UInt64 => u64::read_be(&mut cursor)?.into(),
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    /// Moves past the next key or column.
+    fn skip(&mut self) {
+        if self.id_expected {
+            self.id_expected = false;
+        } else if self.subid_expected {
+            self.subid_expected = false;
+        } else {
+            self.column_idx += 1;
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        !self.id_expected && !self.subid_expected && self.column_idx >= self.fields.len()
+    }
+
+    pub fn new(
+        exh: Rc<Exh>,
+        exd_data: Rc<[u8]>,
+        fields: Rc<[ExField]>,
+        id: u32,
+        subid: Option<u16>,
+        offset: u64,
+    ) -> Self {
         Self {
             exh,
             exd_data,
@@ -281,10 +905,22 @@ impl ExdRowReader {
             id_expected: true,
             subid: subid.unwrap_or(0),
             subid_expected: subid.is_some(),
+            has_subid: subid.is_some(),
             offset,
+            fields,
             column_idx: 0,
+            names: None,
+            last_column: None,
         }
     }
+
+    /// Moves to a key or column by its position in `names`.
+    fn seek(&mut self, pos: usize) {
+        let key_count = 1 + usize::from(self.has_subid);
+        self.id_expected = pos == 0;
+        self.subid_expected = self.has_subid && pos == 1;
+        self.column_idx = pos.saturating_sub(key_count);
+    }
 }
 
 #[derive(Debug)]
@@ -316,6 +952,12 @@ impl From<binrw::Error> for ExdDeserializerError {
     }
 }
 
+impl From<XivError> for ExdDeserializerError {
+    fn from(source: XivError) -> Self {
+        Self(source.to_string().into_boxed_str())
+    }
+}
+
 impl<'de> de::Deserializer<'de> for &mut ExdRowReader {
     type Error = ExdDeserializerError;
 
@@ -324,6 +966,15 @@ impl<'de> de::Deserializer<'de> for &mut ExdRowReader {
     }
 
     fn deserialize_any<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        if let Some(raw) = self.next_string()? {
+            // plain strings are visited as such to keep `Value::String` for them
+            return match String::from_utf8(raw) {
+                Ok(s) if !s.contains('\x02') => v.visit_string(s),
+                Ok(s) => v.visit_byte_buf(s.into_bytes()),
+                Err(e) => v.visit_byte_buf(e.into_bytes()),
+            };
+        }
+
         if self.id_expected {
             self.id_expected = false;
             v.visit_u32(self.id)
@@ -331,17 +982,20 @@ impl<'de> de::Deserializer<'de> for &mut ExdRowReader {
             self.subid_expected = false;
             v.visit_u16(self.subid)
         } else {
-            let column = self
-                .exh
-                .columns
-                .get(self.column_idx)
+            let (idx, column, mask) = self
+                .next_column()
                 .ok_or_else(|| ExdDeserializerError("not enough columns in exd file".into()))?;
 
             let mut cursor = Cursor::new(&self.exd_data);
             cursor.seek(SeekFrom::Start(self.offset + column.offset as u64))?;
+            let vtype = column.vtype;
+            self.last_column = Some(idx);
             self.column_idx += 1;
 
-            match column.vtype {
+            if let Some(mask) = mask {
+                return v.visit_u8(u8::read_be(&mut cursor)? & mask);
+            }
+            match vtype {
                 ValueType::Int8 => v.visit_i8(i8::read_be(&mut cursor)?),
                 ValueType::Int16 => v.visit_i16(i16::read_be(&mut cursor)?),
                 ValueType::Int32 => v.visit_i32(i32::read_be(&mut cursor)?),
@@ -352,67 +1006,257 @@ impl<'de> de::Deserializer<'de> for &mut ExdRowReader {
                 ValueType::UInt64 => v.visit_u64(u64::read_be(&mut cursor)?),
                 ValueType::Float32 => v.visit_f32(f32::read_be(&mut cursor)?),
                 ValueType::Bool => v.visit_bool(u8::read_be(&mut cursor)? != 0),
-                ValueType::PackedBool0 => v.visit_bool(u8::read_be(&mut cursor)? & 1 != 0),
-                ValueType::PackedBool1 => v.visit_bool(u8::read_be(&mut cursor)? & 2 != 0),
-                ValueType::PackedBool2 => v.visit_bool(u8::read_be(&mut cursor)? & 3 != 0),
-                ValueType::PackedBool3 => v.visit_bool(u8::read_be(&mut cursor)? & 4 != 0),
-                ValueType::PackedBool4 => v.visit_bool(u8::read_be(&mut cursor)? & 5 != 0),
-                ValueType::PackedBool5 => v.visit_bool(u8::read_be(&mut cursor)? & 6 != 0),
-                ValueType::PackedBool6 => v.visit_bool(u8::read_be(&mut cursor)? & 7 != 0),
-                ValueType::PackedBool7 => v.visit_bool(u8::read_be(&mut cursor)? & 8 != 0),
-                ValueType::String => {
-                    let str_offset = u32::read_be(&mut cursor)?;
-                    let abs_offset = self.offset + self.exh.data_offset as u64 + str_offset as u64;
-                    cursor.seek(SeekFrom::Start(abs_offset))?;
-                    v.visit_string(binrw::NullString::read(&mut cursor)?.to_string())
+                ValueType::PackedBool0
+                | ValueType::PackedBool1
+                | ValueType::PackedBool2
+                | ValueType::PackedBool3
+                | ValueType::PackedBool4
+                | ValueType::PackedBool5
+                | ValueType::PackedBool6
+                | ValueType::PackedBool7 => {
+                    let mask = vtype.packed_bit_mask().unwrap();
+                    v.visit_bool(u8::read_be(&mut cursor)? & mask != 0)
                 }
+                ValueType::String => unreachable!("string columns are read by next_string"),
             }
         }
     }
 
+    fn deserialize_str<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        match self.next_string()? {
+            Some(raw) => v.visit_string(SeString::parse(&raw)?.to_plain_text()),
+            None => self.deserialize_any(v),
+        }
+    }
+
+    #[inline]
+    fn deserialize_string<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(v)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        match self.next_string()? {
+            Some(raw) => v.visit_byte_buf(raw),
+            None => self.deserialize_any(v),
+        }
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(v)
+    }
+
+    /// Every column has a value, so `Option<T>` is always `Some`. Links
+    /// where `0` means none are read with `zero_as_none` instead.
+    #[inline]
+    fn deserialize_option<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        v.visit_some(self)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        v: V,
+    ) -> Result<V::Value, Self::Error> {
+        v.visit_newtype_struct(self)
+    }
+
+    /// Reads every remaining column.
     #[inline]
     fn deserialize_seq<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
-        v.visit_seq(self)
+        v.visit_seq(ColumnSeq {
+            row: self,
+            len: None,
+        })
+    }
+
+    /// Reads `len` consecutive columns, e.g. into `[u8; 8]`.
+    #[inline]
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        v: V,
+    ) -> Result<V::Value, Self::Error> {
+        v.visit_seq(ColumnSeq {
+            row: self,
+            len: Some(len),
+        })
     }
 
     #[inline]
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        v: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, v)
+    }
+
+    /// Reads one column per field, so structs can be nested. Fields marked
+    /// with `#[serde(skip)]` do not take a column.
+    ///
+    /// When reading by column names, the row itself is read as a map instead.
     fn deserialize_struct<V: de::Visitor<'de>>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.names.is_some() {
+            true => self.deserialize_map(v),
+            false => self.deserialize_tuple(fields.len(), v),
+        }
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, v: V) -> Result<V::Value, Self::Error> {
+        match self.names.take() {
+            Some(names) => v.visit_map(ColumnMap {
+                row: self,
+                names,
+                pos: 0,
+            }),
+            None => self.deserialize_any(v),
+        }
+    }
+
+    /// Maps an integer column to unit variant with the same index.
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
         v: V,
     ) -> Result<V::Value, Self::Error> {
-        self.deserialize_seq(v)
+        let index = self
+            .peek_integer()?
+            .and_then(|i| u32::try_from(i).ok())
+            .ok_or_else(|| {
+                ExdDeserializerError(format!("{name} needs a non-negative integer column").into())
+            })?;
+        self.skip();
+        v.visit_enum(index.into_deserializer())
     }
 
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct tuple
-        tuple_struct map enum identifier ignored_any
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        unit unit_struct identifier ignored_any
     }
 }
 
-impl<'de> de::SeqAccess<'de> for ExdRowReader {
+/// Consecutive columns of a row, up to `len` if set.
+struct ColumnSeq<'a> {
+    row: &'a mut ExdRowReader,
+    len: Option<usize>,
+}
+
+impl<'de> de::SeqAccess<'de> for ColumnSeq<'_> {
     type Error = ExdDeserializerError;
 
     fn next_element_seed<T: de::DeserializeSeed<'de>>(
         &mut self,
         seed: T,
     ) -> Result<Option<T::Value>, Self::Error> {
-        if self.column_idx < self.exh.columns.len() {
-            seed.deserialize(self).map(Some)
-        } else {
-            Ok(None)
+        match &mut self.len {
+            Some(0) => return Ok(None),
+            Some(len) => *len -= 1,
+            None if self.row.is_done() => return Ok(None),
+            None => {}
         }
+        seed.deserialize(&mut *self.row).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.len
+    }
+}
+
+/// Keys and columns of a row by their names, each value starting at its own
+/// column (so `[u8; 3]` value reads 3 consecutive columns).
+struct ColumnMap<'a> {
+    row: &'a mut ExdRowReader,
+    names: Rc<[Box<str>]>,
+    pos: usize,
+}
+
+impl<'de> de::MapAccess<'de> for ColumnMap<'_> {
+    type Error = ExdDeserializerError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.names.get(self.pos) {
+            Some(name) => seed
+                .deserialize(name.as_ref().into_deserializer())
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.row.seek(self.pos);
+        self.pos += 1;
+        seed.deserialize(&mut *self.row)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.names.len() - self.pos)
+    }
+}
+
+/// Placeholder for `N` columns which are not of interest, e.g. `unk: Skip<3>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Skip<const N: usize>;
+
+impl<'de, const N: usize> Deserialize<'de> for Skip<N> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SkipVisitor<const N: usize>;
+
+        impl<'de, const N: usize> de::Visitor<'de> for SkipVisitor<N> {
+            type Value = Skip<N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{N} columns")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                for i in 0..N {
+                    if seq.next_element::<de::IgnoredAny>()?.is_none() {
+                        return Err(de::Error::invalid_length(i, &self));
+                    }
+                }
+                Ok(Skip)
+            }
+        }
+
+        deserializer.deserialize_tuple(N, SkipVisitor)
+    }
+}
+
+impl<const N: usize> Serialize for Skip<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeTuple;
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for _ in 0..N {
+            tuple.serialize_element(&())?;
+        }
+        tuple.end()
     }
 }
 
 struct ExdPageReader<T> {
     row_type: PhantomData<T>,
     exh: Rc<Exh>,
-    exd_fileptr: InnerFilePtr,
+    exd_fileptr: Option<InnerFilePtr>,
     exd_data: Option<Rc<[u8]>>,
     exd_header: Option<Rc<ExdHeader>>,
+    fields: Rc<[ExField]>,
+    names: Option<Rc<[Box<str>]>>,
+    location: ExdLocation,
     row_index: usize,
     subrow_index: u16,
     subrow_count: u16,
@@ -424,12 +1268,35 @@ where
     T: Sized + Deserialize<'de>,
 {
     pub fn new(exh: Rc<Exh>, exd_fileptr: InnerFilePtr) -> Self {
+        let fields = ExdReadOptions::default().fields(&exh);
         Self {
             row_type: PhantomData,
             exh,
-            exd_fileptr,
+            exd_fileptr: Some(exd_fileptr),
             exd_data: None,
             exd_header: None,
+            fields,
+            names: None,
+            location: ExdLocation::default(),
+            row_index: 0,
+            subrow_index: 0,
+            subrow_count: 0,
+            done: false,
+        }
+    }
+
+    /// Reads rows of an already loaded .exd file.
+    pub fn from_data(exh: Rc<Exh>, exd_data: Rc<[u8]>) -> Self {
+        let fields = ExdReadOptions::default().fields(&exh);
+        Self {
+            row_type: PhantomData,
+            exh,
+            exd_fileptr: None,
+            exd_data: Some(exd_data),
+            exd_header: None,
+            fields,
+            names: None,
+            location: ExdLocation::default(),
             row_index: 0,
             subrow_index: 0,
             subrow_count: 0,
@@ -437,10 +1304,50 @@ where
         }
     }
 
+    /// Presents rows the way `options` ask for.
+    pub fn with_options(mut self, options: &ExdReadOptions) -> Self {
+        self.fields = options.fields(&self.exh);
+        self.names = options.by_name.then(|| {
+            let names = self.exh.column_names(options.schema);
+            let key_count = names.len() - self.exh.columns.len();
+            let keys = names[..key_count].iter().cloned();
+            let fields = self
+                .fields
+                .iter()
+                .map(|f| names[key_count + f.column()].clone());
+            keys.chain(fields).collect()
+        });
+        self
+    }
+
+    /// Names sheet, locale and path of the page to report errors with.
+    pub fn with_location(mut self, sheet: &str, locale: Locale, path: &str) -> Self {
+        self.location = ExdLocation {
+            sheet: Some(sheet.into()),
+            locale: Some(locale),
+            path: Some(path.into()),
+            ..Default::default()
+        };
+        self
+    }
+
+    fn location(&self, row_id: u32, subrow_id: Option<u16>) -> Box<ExdLocation> {
+        Box::new(ExdLocation {
+            row_id: Some(row_id),
+            subrow_id,
+            ..self.location.clone()
+        })
+    }
+
     fn lazy_exd_data(&mut self) -> Result<Rc<[u8]>, XivError> {
         assert!(!self.done);
         if self.exd_data.is_none() {
-            let exd_file = self.exd_fileptr.read_plain()?;
+            let exd_file = self
+                .exd_fileptr
+                .as_ref()
+                .unwrap()
+                .read_plain()
+                .map_err(|e| XivError::ExdFile(Box::new(self.location.clone()), Box::new(e)))?;
             self.exd_data = Some(exd_file.into());
         }
         Ok(self.exd_data.as_ref().unwrap().clone())
@@ -450,7 +1357,7 @@ where
         assert!(!self.done);
         if self.exd_header.is_none() {
             let header = ExdHeader::read(&mut Cursor::new(self.lazy_exd_data()?))
-                .map_err(XivError::ExdFileHeader)?;
+                .map_err(|e| XivError::ExdFileHeader(Box::new(self.location.clone()), e))?;
             self.exd_header = Some(Rc::new(header));
         }
         Ok(self.exd_header.as_ref().unwrap().clone())
@@ -464,10 +1371,11 @@ where
             let mut cursor = Cursor::new(self.lazy_exd_data()?);
             cursor
                 .seek(SeekFrom::Start(row_ptr.offset as u64))
-                .map_err(XivError::ExdSeek)?;
+                .map_err(|e| XivError::ExdSeek(self.location(row_ptr.id, None), e))?;
 
-            let _size = u32::read_be(&mut cursor).map_err(XivError::ExdRowHeader)?;
-            self.subrow_count = u16::read_be(&mut cursor).map_err(XivError::ExdRowHeader)?;
+            let row_header = |e| XivError::ExdRowHeader(self.location(row_ptr.id, None), e);
+            let _size = u32::read_be(&mut cursor).map_err(row_header)?;
+            self.subrow_count = u16::read_be(&mut cursor).map_err(row_header)?;
 
             let row = self.read_next_subrow()?;
             if self.subrow_index >= self.subrow_count {
@@ -504,23 +1412,34 @@ where
                 .seek(SeekFrom::Start(
                     row_ptr.offset as u64 + row_header_size + subrow_offset,
                 ))
-                .map_err(XivError::ExdSeek)?;
+                .map_err(|e| XivError::ExdSeek(self.location(row_ptr.id, None), e))?;
 
-            let subid = match self.exh.variant {
-                ExVariant::Normal => None,
-                ExVariant::SubRows => {
-                    Some(u16::read_be(&mut cursor).map_err(XivError::ExdSubRowHeader)?)
-                }
-            };
+            let subid =
+                match self.exh.variant {
+                    ExVariant::Normal => None,
+                    ExVariant::SubRows => Some(u16::read_be(&mut cursor).map_err(|e| {
+                        XivError::ExdSubRowHeader(self.location(row_ptr.id, None), e)
+                    })?),
+                };
 
-            let row = T::deserialize(&mut ExdRowReader::new(
+            let mut row_reader = ExdRowReader::new(
                 self.exh.clone(),
                 self.lazy_exd_data()?,
+                self.fields.clone(),
                 row_ptr.id,
                 subid,
                 cursor.position(),
-            ))
-            .map_err(|e| XivError::ExdDeserialization(e.0))?;
+            );
+            row_reader.names = self.names.clone();
+            let row = T::deserialize(&mut row_reader).map_err(|e| {
+                let mut location = self.location(row_ptr.id, subid);
+                location.column = row_reader.last_column;
+                location.vtype = row_reader
+                    .last_column
+                    .and_then(|idx| self.exh.columns.get(idx))
+                    .map(|column| column.vtype);
+                XivError::ExdDeserialization(location, e.0)
+            })?;
 
             self.subrow_index += 1;
             Ok(Some(row))
@@ -554,9 +1473,18 @@ where
 
 impl<'de, T> FusedIterator for ExdPageReader<T> where T: Sized + Deserialize<'de> {}
 
+/// Path of sheet's .exh file within SqPack repository.
+pub fn exh_path(base_path: &str) -> String {
+    format!("exd/{}.exh", base_path.to_lowercase())
+}
+
+/// Path of sheet's .exd page file within SqPack repository.
+pub fn exd_path(base_path: &str, start_id: u32, locale: Locale) -> String {
+    format!("exd/{}_{start_id}{locale}.exd", base_path.to_lowercase())
+}
+
 pub fn read_exh(repo: Arc<SqPack>, base_path: &str) -> Result<Exh, XivError> {
-    let base_path = base_path.to_lowercase();
-    let exh_path = format!("exd/{base_path}.exh").into_boxed_str();
+    let exh_path = exh_path(base_path).into_boxed_str();
     let exh_file = repo
         .find(&exh_path)?
         .ok_or(XivError::ExhNotFound(exh_path))?
@@ -565,6 +1493,51 @@ pub fn read_exh(repo: Arc<SqPack>, base_path: &str) -> Result<Exh, XivError> {
     Exh::read(&mut Cursor::new(exh_file)).map_err(XivError::Exh)
 }
 
+/// How rows of a sheet are presented to the type they are read into.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExdReadOptions<'a> {
+    /// Reads rows as maps keyed by names of their keys and columns (see
+    /// `Exh::column_names`) rather than by column order.
+    pub by_name: bool,
+    /// Names of columns when reading by name, `col_N` without it.
+    pub schema: Option<&'a SheetSchema>,
+    /// Reads packed bool columns sharing a byte as a single `u8` value (see
+    /// `Exh::fields`), which `ExdWriter::with_bitfields` writes back.
+    pub bitfields: bool,
+}
+
+impl ExdReadOptions<'_> {
+    fn fields(&self, exh: &Exh) -> Rc<[ExField]> {
+        match self.bitfields {
+            true => exh.fields().into(),
+            false => (0..exh.columns.len()).map(ExField::Column).collect(),
+        }
+    }
+}
+
+/// Finds page files of a sheet, returning readers of their rows.
+fn find_exd_pages<'de, T>(
+    repo: &SqPack,
+    exh: &Rc<Exh>,
+    base_path: &str,
+    locale: Locale,
+    options: &ExdReadOptions,
+) -> Result<Vec<ExdPageReader<T>>, XivError>
+where
+    T: Sized + Deserialize<'de>,
+{
+    let mut pages = Vec::with_capacity(exh.pages.len());
+    for page in &exh.pages {
+        let exd_path = exd_path(base_path, page.start_id, locale).into_boxed_str();
+        let exd_fileptr = repo
+            .find(&exd_path)?
+            .ok_or_else(|| XivError::ExdNotFound(exd_path.clone()))?;
+        let reader = ExdPageReader::new(exh.clone(), exd_fileptr).with_options(options);
+        pages.push(reader.with_location(base_path, locale, &exd_path));
+    }
+    Ok(pages)
+}
+
 pub fn read_exd<'de, T>(
     repo: Arc<SqPack>,
     base_path: &str,
@@ -572,28 +1545,867 @@ pub fn read_exd<'de, T>(
 ) -> Result<impl Iterator<Item = Result<T, XivError>>, XivError>
 where
     T: Sized + Serialize + Deserialize<'de> + 'static,
+{
+    read_exd_with(repo, base_path, locale, ExdReadOptions::default())
+}
+
+/// Reads rows of a sheet, presented the way `options` ask for.
+pub fn read_exd_with<'de, T>(
+    repo: Arc<SqPack>,
+    base_path: &str,
+    locale: Locale,
+    options: ExdReadOptions,
+) -> Result<impl Iterator<Item = Result<T, XivError>>, XivError>
+where
+    T: Sized + Deserialize<'de> + 'static,
 {
     let base_path = base_path.to_lowercase();
     let exh = Rc::new(read_exh(repo.clone(), &base_path)?);
     let exd_locale = exh
-        .languages
-        .iter()
-        .cloned()
-        .find(|l| *l == locale)
-        .or(exh.languages.first().cloned())
-        .unwrap_or(Locale::None);
-
-    let mut fileptrs = Vec::with_capacity(exh.pages.len());
+        .resolve_locale(locale)
+        .ok_or_else(|| XivError::ExdLocaleNotFound(base_path.as_str().into(), locale))?;
+    let pages = find_exd_pages(&repo, &exh, &base_path, exd_locale, &options)?;
+
+    Ok(pages.into_iter().flatten())
+}
+
+/// Reads rows of an .exd page file loaded from elsewhere than SqPack repository.
+pub fn read_exd_data<'de, T>(
+    exh: Rc<Exh>,
+    exd_data: Rc<[u8]>,
+) -> impl Iterator<Item = Result<T, XivError>>
+where
+    T: Sized + Serialize + Deserialize<'de> + 'static,
+{
+    read_exd_data_with(exh, exd_data, ExdReadOptions::default())
+}
+
+/// Reads rows of an .exd page file, presented the way `options` ask for.
+pub fn read_exd_data_with<'de, T>(
+    exh: Rc<Exh>,
+    exd_data: Rc<[u8]>,
+    options: ExdReadOptions,
+) -> impl Iterator<Item = Result<T, XivError>>
+where
+    T: Sized + Deserialize<'de> + 'static,
+{
+    ExdPageReader::from_data(exh, exd_data).with_options(&options)
+}
+
+/// Contents of an .exd page file, along with where it was read from.
+pub struct ExdPageFile {
+    pub location: ExdLocation,
+    pub data: Box<[u8]>,
+}
+
+/// Reads .exd page files of a sheet one at a time, for rows to be borrowed
+/// from them with `row_ref::page_rows`.
+pub fn read_exd_pages(
+    repo: Arc<SqPack>,
+    exh: &Exh,
+    base_path: &str,
+    locale: Locale,
+) -> Result<impl Iterator<Item = Result<ExdPageFile, XivError>>, XivError> {
+    let base_path = base_path.to_lowercase();
+    let exd_locale = exh
+        .resolve_locale(locale)
+        .ok_or_else(|| XivError::ExdLocaleNotFound(base_path.as_str().into(), locale))?;
+
+    let mut files = Vec::with_capacity(exh.pages.len());
     for page in &exh.pages {
-        let start_id = page.start_id;
-        let exd_path = format!("exd/{base_path}_{start_id}{exd_locale}.exd").into_boxed_str();
+        let exd_path = exd_path(&base_path, page.start_id, exd_locale).into_boxed_str();
         let exd_fileptr = repo
             .find(&exd_path)?
-            .ok_or(XivError::ExdNotFound(exd_path))?;
-        fileptrs.push(exd_fileptr);
+            .ok_or_else(|| XivError::ExdNotFound(exd_path.clone()))?;
+        let location = ExdLocation {
+            sheet: Some(base_path.as_str().into()),
+            locale: Some(exd_locale),
+            path: Some(exd_path),
+            ..Default::default()
+        };
+        files.push((location, exd_fileptr));
     }
 
-    Ok(fileptrs
+    Ok(files
         .into_iter()
-        .flat_map(move |fileptr| ExdPageReader::new(exh.clone(), fileptr)))
+        .map(|(location, exd_fileptr)| match exd_fileptr.read_plain() {
+            Ok(data) => Ok(ExdPageFile { location, data }),
+            Err(e) => Err(XivError::ExdFile(Box::new(location), Box::new(e))),
+        }))
+}
+
+/// Reads rows into structs by column names rather than by column order.
+///
+/// Keys are named `id` and `subid`, columns by `schema` or `col_N`, so a
+/// struct may only declare fields it needs with `#[serde(rename = "...")]`.
+pub fn read_exd_named<'de, T>(
+    repo: Arc<SqPack>,
+    base_path: &str,
+    locale: Locale,
+    schema: Option<&SheetSchema>,
+) -> Result<impl Iterator<Item = Result<T, XivError>>, XivError>
+where
+    T: Sized + Deserialize<'de> + 'static,
+{
+    let options = ExdReadOptions {
+        by_name: true,
+        schema,
+        ..Default::default()
+    };
+    read_exd_with(repo, base_path, locale, options)
+}
+
+/// Reads rows of an .exd page file by column names, see `read_exd_named`.
+pub fn read_exd_data_named<'de, T>(
+    exh: Rc<Exh>,
+    exd_data: Rc<[u8]>,
+    schema: Option<&SheetSchema>,
+) -> impl Iterator<Item = Result<T, XivError>>
+where
+    T: Sized + Deserialize<'de> + 'static,
+{
+    let options = ExdReadOptions {
+        by_name: true,
+        schema,
+        ..Default::default()
+    };
+    read_exd_data_with(exh, exd_data, options)
+}
+
+/// Reads every locale listed in `Exh::languages` of a sheet in a single pass,
+/// merging string columns of rows with the same id and subrow id.
+///
+/// Rows missing from some locales are still returned, with empty strings for
+/// those locales.
+pub fn read_exd_localized(
+    repo: Arc<SqPack>,
+    base_path: &str,
+) -> Result<impl Iterator<Item = Result<LocalizedRow, XivError>>, XivError> {
+    let base_path = base_path.to_lowercase();
+    let exh = Rc::new(read_exh(repo.clone(), &base_path)?);
+    let locales: Box<[Locale]> = exh.languages.clone().into();
+
+    let mut readers = Vec::with_capacity(locales.len());
+    for locale in locales.iter().copied() {
+        let options = ExdReadOptions::default();
+        let reader = find_exd_pages::<Row>(&repo, &exh, &base_path, locale, &options)?
+            .into_iter()
+            .flatten();
+        readers.push(reader);
+    }
+    Ok(merge_localized_pages(exh, locales, readers))
+}
+
+/// Merges rows read in each of `locales` by their keys. Rows of every locale
+/// are expected in ascending order of keys, as they are laid out in pages.
+fn merge_localized_pages<I>(
+    exh: Rc<Exh>,
+    locales: Box<[Locale]>,
+    readers: Vec<I>,
+) -> impl Iterator<Item = Result<LocalizedRow, XivError>>
+where
+    I: Iterator<Item = Result<Row, XivError>>,
+{
+    let mut readers: Vec<_> = readers.into_iter().map(Iterator::peekable).collect();
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut key = None;
+        for reader in readers.iter_mut() {
+            match reader.peek() {
+                Some(Ok(row)) => {
+                    let row_key = row_key(row, exh.variant);
+                    key = Some(key.map_or(row_key, |key: (u32, u16)| key.min(row_key)));
+                }
+                Some(Err(_)) => {
+                    done = true;
+                    return reader.next().map(|r| Err(r.unwrap_err()));
+                }
+                None => {}
+            }
+        }
+        let Some(key) = key else {
+            done = true;
+            return None;
+        };
+
+        let rows = readers
+            .iter_mut()
+            .map(|reader| {
+                reader
+                    .next_if(|row| {
+                        row.as_ref()
+                            .is_ok_and(|row| row_key(row, exh.variant) == key)
+                    })
+                    .and_then(Result::ok)
+            })
+            .collect();
+        Some(Ok(merge_localized_rows(&exh, &locales, rows)))
+    })
+}
+
+/// Row id and subrow id (0 for sheets without subrows) of a row read as `Row`.
+fn row_key(row: &Row, variant: ExVariant) -> (u32, u16) {
+    let id = row.first().and_then(Value::as_u32).unwrap_or_default();
+    let subid = match variant {
+        ExVariant::Normal => None,
+        ExVariant::SubRows => row.get(1).and_then(Value::as_u32),
+    };
+    (id, subid.unwrap_or_default() as u16)
+}
+
+/// Merges a row read in each of `locales`, `None` where a locale lacks it.
+/// Shared values are taken from the first locale having the row.
+fn merge_localized_rows(exh: &Exh, locales: &[Locale], rows: Vec<Option<Row>>) -> LocalizedRow {
+    let key_len = match exh.variant {
+        ExVariant::Normal => 1,
+        ExVariant::SubRows => 2,
+    };
+
+    let mut columns: Vec<_> = rows
+        .into_iter()
+        .map(|row| row.unwrap_or_default().into_iter())
+        .collect();
+    let mut merged = LocalizedRow::with_capacity(key_len + exh.columns.len());
+    for column_idx in 0..key_len + exh.columns.len() {
+        let is_string = column_idx
+            .checked_sub(key_len)
+            .and_then(|i| exh.columns.get(i))
+            .is_some_and(|c| c.vtype == ValueType::String);
+
+        let values: Vec<_> = columns.iter_mut().map(Iterator::next).collect();
+        if is_string {
+            let values = locales
+                .iter()
+                .copied()
+                .zip(values)
+                .map(|(locale, value)| (locale, value.unwrap_or(Value::String("".into()))))
+                .collect();
+            merged.push(LocalizedValue::PerLocale(values));
+        } else if let Some(value) = values.into_iter().flatten().next() {
+            merged.push(LocalizedValue::Shared(value));
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn exh_fixture(variant: u8, data_offset: u16, columns: &[(u16, u16)], rows: u32) -> Exh {
+        Exh::read(&mut Cursor::new(exh_fixture_bytes(
+            variant,
+            data_offset,
+            columns,
+            rows,
+        )))
+        .unwrap()
+    }
+
+    fn exh_fixture_bytes(
+        variant: u8,
+        data_offset: u16,
+        columns: &[(u16, u16)],
+        rows: u32,
+    ) -> Vec<u8> {
+        let mut data = b"EXHF".to_vec();
+        data.extend(3u16.to_be_bytes());
+        data.extend(data_offset.to_be_bytes());
+        data.extend((columns.len() as u16).to_be_bytes());
+        data.extend(1u16.to_be_bytes()); // pages
+        data.extend(1u16.to_be_bytes()); // languages
+        data.extend([0, 0, 0, variant, 0, 0]);
+        data.extend(rows.to_be_bytes());
+        data.extend([0; 8]);
+        for (vtype, offset) in columns {
+            data.extend(vtype.to_be_bytes());
+            data.extend(offset.to_be_bytes());
+        }
+        data.extend(0u32.to_be_bytes());
+        data.extend(rows.to_be_bytes());
+        data.extend([0, 0]); // Locale::None
+        data
+    }
+
+    fn exd_fixture(rows: &[(u32, u16, Vec<u8>)]) -> Rc<[u8]> {
+        let header_len = 32 + rows.len() * 8;
+        let mut data = b"EXDF".to_vec();
+        data.extend(2u16.to_be_bytes());
+        data.extend(0u16.to_be_bytes());
+        data.extend((rows.len() as u32 * 8).to_be_bytes());
+        data.extend([0; 20]);
+        let mut offset = header_len;
+        for (id, _, row) in rows {
+            data.extend(id.to_be_bytes());
+            data.extend((offset as u32).to_be_bytes());
+            offset += 6 + row.len();
+        }
+        for (_, subrows, row) in rows {
+            data.extend((row.len() as u32).to_be_bytes());
+            data.extend(subrows.to_be_bytes());
+            data.extend(row);
+        }
+        data.into()
+    }
+
+    fn read_rows(exh: Exh, exd: Rc<[u8]>) -> Vec<Row> {
+        ExdPageReader::<Row>::from_data(Rc::new(exh), exd)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn locale_codes() {
+        for locale in [Locale::None, Locale::English, Locale::ChineseSimplified] {
+            assert_eq!(locale.code().parse::<Locale>().unwrap(), locale);
+        }
+        let err = XivError::ExdLocaleNotFound("Item".into(), Locale::English);
+        assert_eq!(err.to_string(), "Sheet Item has no en locale");
+    }
+
+    #[test]
+    fn packed_bools() {
+        let columns: Vec<_> = (0..8u16)
+            .map(|bit| (0x19 + bit, 4))
+            .chain([(0x7, 0)])
+            .collect();
+        let exh = exh_fixture(1, 8, &columns, 1);
+        let exd = exd_fixture(&[(7, 1, vec![0, 0, 1, 0, 0b1010_0101, 0, 0, 0])]);
+
+        let rows = read_rows(exh, exd);
+        let bools = [true, false, true, false, false, true, false, true].map(Value::Bool);
+        assert_eq!(rows[0][0], Value::UInt32(7));
+        assert_eq!(rows[0][1..9], bools);
+        assert_eq!(rows[0][9], Value::UInt32(256));
+    }
+
+    #[test]
+    fn bitfield_grouping() {
+        // two bytes of packed bools, interleaved with a plain column
+        let columns = [(0x1A, 1), (0x3, 0), (0x19, 2), (0x20, 1), (0x19, 1)];
+        let exh = exh_fixture(1, 4, &columns, 0);
+
+        let bitfields = exh.bitfields();
+        assert_eq!(bitfields.len(), 2);
+        assert_eq!(bitfields[0].offset, 1);
+        assert_eq!(
+            bitfields[0].columns,
+            [Some(4), Some(0), None, None, None, None, None, Some(3)]
+        );
+        assert_eq!(bitfields[0].mask(), 0b1000_0011);
+        assert_eq!(bitfields[1].offset, 2);
+        assert_eq!(bitfields[1].mask(), 0b1);
+    }
+
+    #[test]
+    fn bitfield_fields() {
+        let columns = [(0x1A, 1), (0x3, 0), (0x19, 2), (0x20, 1), (0x19, 1)];
+        let exh = exh_fixture(1, 4, &columns, 1);
+        let bitfields = exh.bitfields();
+        assert_eq!(
+            exh.fields(),
+            [
+                ExField::Bitfield(bitfields[0].clone()),
+                ExField::Column(1),
+                ExField::Bitfield(bitfields[1].clone()),
+            ]
+        );
+
+        // unused bits are cleared
+        let exd = exd_fixture(&[(7, 1, vec![9, 0xFF, 0xFF, 0])]);
+        let options = ExdReadOptions {
+            bitfields: true,
+            ..Default::default()
+        };
+        let rows: Vec<Row> = read_exd_data_with(Rc::new(exh.clone()), exd, options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let expected = [0b1000_0011, 9, 1].map(Value::UInt8);
+        assert_eq!(rows[0][0], Value::UInt32(7));
+        assert_eq!(rows[0][1..], expected);
+
+        let mut writer = write::ExdWriter::new(exh.clone()).with_bitfields();
+        writer.push(&rows[0]).unwrap();
+        let read = read_rows(exh, writer.pages()[0].data.clone().into());
+        let mut expected = vec![Value::Bool(true); 4];
+        expected.insert(1, Value::UInt8(9));
+        assert_eq!(read[0][1..], expected);
+    }
+
+    #[test]
+    fn strings_and_subrows() {
+        let exh = exh_fixture(2, 6, &[(0x0, 0), (0x1C, 4)], 1);
+        // strings follow all subrows, offsets are relative to the end of each subrow
+        let mut row = Vec::new();
+        row.extend([0, 0, 0, 0, 0, 8, 0b1000, 0]);
+        row.extend([0, 1, 0, 0, 0, 4, 0, 0]);
+        row.extend(b"foo\0\0");
+        let exd = exd_fixture(&[(3, 2, row)]);
+
+        let rows = read_rows(exh, exd);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0],
+            [
+                Value::UInt32(3),
+                Value::UInt16(0),
+                Value::String("foo".into()),
+                Value::Bool(true)
+            ]
+        );
+        assert_eq!(
+            rows[1],
+            [
+                Value::UInt32(3),
+                Value::UInt16(1),
+                Value::String("".into()),
+                Value::Bool(false)
+            ]
+        );
+    }
+
+    #[test]
+    fn localized_rows_by_key() {
+        let exh = Rc::new(exh_fixture(1, 4, &[(0x0, 0)], 3));
+        let row = |id: u32, text: &str| {
+            let mut data = vec![0, 0, 0, 0];
+            data.extend(text.as_bytes());
+            data.push(0);
+            (id, 1, data)
+        };
+        let en = exd_fixture(&[row(1, "one"), row(2, "two"), row(3, "three")]);
+        let de = exd_fixture(&[row(1, "eins"), row(3, "drei")]);
+        let readers = [en, de]
+            .map(|exd| ExdPageReader::<Row>::from_data(exh.clone(), exd))
+            .into();
+        let locales = [Locale::English, Locale::German].into();
+
+        let rows: Vec<_> = merge_localized_pages(exh, locales, readers)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let text = |row: &LocalizedRow, locale| row[1].get(locale).unwrap().to_string();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][0], LocalizedValue::Shared(Value::UInt32(2)));
+        assert_eq!(text(&rows[1], Locale::English), "two");
+        assert_eq!(text(&rows[1], Locale::German), "");
+        assert_eq!(rows[2][0], LocalizedValue::Shared(Value::UInt32(3)));
+        assert_eq!(text(&rows[2], Locale::German), "drei");
+    }
+
+    #[test]
+    fn write_exh() {
+        let data = exh_fixture_bytes(2, 12, &[(0x0, 0), (0x1C, 4), (0x6, 8)], 3);
+        let exh = Exh::read(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(&*write::ExhWriter::new(&exh).to_bytes(), &data[..]);
+    }
+
+    #[test]
+    fn write_exd_round_trip() {
+        let columns = [
+            (0x0, 0),
+            (0x1A, 4),
+            (0x19, 4),
+            (0x4, 6),
+            (0x0, 8),
+            (0x9, 12),
+        ];
+        let exh = exh_fixture(1, 16, &columns, 3);
+        let rows: Vec<Row> = vec![
+            vec![
+                Value::UInt32(1),
+                Value::String("a".into()),
+                Value::Bool(true),
+                Value::Bool(false),
+                Value::Int16(-2),
+                Value::String("".into()),
+                Value::Float(0.5),
+            ],
+            vec![
+                Value::UInt32(5),
+                Value::String("bc".into()),
+                Value::Bool(false),
+                Value::Bool(true),
+                Value::Int16(300),
+                Value::String("def".into()),
+                Value::Float(-1.0),
+            ],
+        ];
+
+        let mut writer = write::ExdWriter::new(exh.clone());
+        for row in &rows {
+            writer.push(row).unwrap();
+        }
+        assert!(
+            writer.push(&rows[0]).is_err(),
+            "duplicate rows are rejected"
+        );
+        let pages = writer.pages();
+        assert_eq!(pages.len(), 1);
+
+        let read = read_rows(exh.clone(), pages[0].data.clone().into());
+        assert_eq!(read, rows);
+
+        // re-encoding rows which were read back gives the same file
+        let mut writer = write::ExdWriter::new(exh);
+        for row in &read {
+            writer.push(row).unwrap();
+        }
+        assert_eq!(writer.pages()[0].data, pages[0].data);
+    }
+
+    #[test]
+    fn write_exd_subrows_and_pages() {
+        let exh = exh_fixture(2, 6, &[(0x0, 0), (0x1C, 4)], 1);
+        let rows: Vec<Row> = vec![
+            vec![
+                Value::UInt32(3),
+                Value::UInt16(0),
+                Value::String("foo".into()),
+                Value::Bool(true),
+            ],
+            vec![
+                Value::UInt32(3),
+                Value::UInt16(1),
+                Value::String("".into()),
+                Value::Bool(false),
+            ],
+            vec![
+                Value::UInt32(9),
+                Value::UInt16(0),
+                Value::String("bar".into()),
+                Value::Bool(false),
+            ],
+        ];
+
+        let mut writer = write::ExdWriter::new(exh.clone());
+        for row in &rows {
+            writer.push(row).unwrap();
+        }
+        writer.repaginate(1);
+        assert_eq!(writer.exh().row_count, 2);
+        let pages = writer.pages();
+        assert_eq!(pages.iter().map(|p| p.start_id).collect::<Vec<_>>(), [3, 9]);
+
+        let read: Vec<Row> = pages
+            .iter()
+            .flat_map(|p| read_rows(exh.clone(), p.data.clone().into()))
+            .collect();
+        assert_eq!(read, rows);
+
+        writer.push(&(10u32, 0u16, "baz", true)).unwrap();
+        writer.recount_pages();
+        let pages: Vec<_> = writer
+            .exh()
+            .pages
+            .iter()
+            .map(|p| (p.start_id, p.row_count))
+            .collect();
+        assert_eq!(pages, [(3, 1), (9, 2)]);
+        assert_eq!(writer.exh().row_count, 3);
+    }
+
+    #[test]
+    fn write_exd_type_checks() {
+        let exh = exh_fixture(1, 4, &[(0x3, 0)], 1);
+        let mut writer = write::ExdWriter::new(exh);
+        assert!(writer.push(&(1u32, 300u16)).is_err(), "300 does not fit u8");
+        assert!(writer.push(&(1u32, "str")).is_err());
+        assert!(writer.push(&(1u32,)).is_err(), "missing column");
+        assert!(writer.push(&(1u32, 255u32)).is_ok());
+    }
+
+    #[test]
+    fn typed_rows() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        enum Slot {
+            None,
+            MainHand,
+            OffHand,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Stat {
+            param: u8,
+            value: i16,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct TypedItem {
+            id: u32,
+            name: String,
+            slot: Slot,
+            #[serde(with = "zero_as_none")]
+            category: Option<u8>,
+            models: [u8; 3],
+            unk: Skip<1>,
+            stats: [Stat; 2],
+            #[serde(skip)]
+            cached: bool,
+            can_be_hq: bool,
+        }
+
+        let columns = [
+            (0x0, 0),
+            (0x3, 4),
+            (0x3, 5),
+            (0x3, 6),
+            (0x3, 7),
+            (0x3, 8),
+            (0x3, 9),
+            (0x3, 10),
+            (0x4, 12),
+            (0x3, 11),
+            (0x4, 14),
+            (0x19, 16),
+        ];
+        let exh = exh_fixture(1, 20, &columns, 2);
+        let items = [
+            TypedItem {
+                id: 1,
+                name: "Sword".into(),
+                slot: Slot::MainHand,
+                category: None,
+                models: [1, 2, 3],
+                unk: Skip,
+                stats: [
+                    Stat {
+                        param: 1,
+                        value: -5,
+                    },
+                    Stat {
+                        param: 2,
+                        value: 300,
+                    },
+                ],
+                cached: false,
+                can_be_hq: true,
+            },
+            TypedItem {
+                id: 2,
+                name: "Shield".into(),
+                slot: Slot::OffHand,
+                category: Some(11),
+                models: [4, 5, 6],
+                unk: Skip,
+                stats: [Stat { param: 0, value: 0 }, Stat { param: 3, value: 1 }],
+                cached: false,
+                can_be_hq: false,
+            },
+        ];
+        let mut writer = write::ExdWriter::new(exh.clone());
+        for item in &items {
+            writer.push(item).unwrap();
+        }
+        let exd: Rc<[u8]> = writer.pages().remove(0).data.into();
+
+        let rows = read_rows(exh.clone(), exd.clone());
+        assert_eq!(rows[0][3], Value::UInt8(0));
+        assert_eq!(rows[1][9], Value::Int16(0));
+
+        let typed: Vec<TypedItem> = ExdPageReader::from_data(Rc::new(exh.clone()), exd)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(typed, items);
+
+        let mut writer = write::ExdWriter::new(exh.clone());
+        let mut row = rows[0].clone();
+        row[2] = Value::UInt8(7);
+        writer.push(&row).unwrap();
+        let exd: Rc<[u8]> = writer.pages().remove(0).data.into();
+        let mut reader = ExdPageReader::<TypedItem>::from_data(Rc::new(exh), exd);
+        match reader.next().unwrap() {
+            Err(XivError::ExdDeserialization(location, _)) => {
+                assert_eq!(location.row_id, Some(1));
+                assert_eq!(location.column, Some(1));
+                assert_eq!(location.vtype, Some(ValueType::UInt8));
+                assert_eq!(location.to_string(), "row 1, column 1 (u8)");
+            }
+            other => panic!("no variant with index 7, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn options() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Linked {
+            id: u32,
+            plain: Option<(u8, u16)>,
+            #[serde(with = "zero_as_none")]
+            link: Option<(u8, u16)>,
+            next: Option<u8>,
+        }
+
+        let columns = [(0x3, 0), (0x4, 2), (0x3, 1), (0x4, 4), (0x3, 6)];
+        let exh = exh_fixture(1, 8, &columns, 1);
+        let exd = exd_fixture(&[(1, 1, vec![0, 0, 0, 9, 0, 0, 0, 0])]);
+        let rows: Vec<Linked> = ExdPageReader::from_data(Rc::new(exh), exd)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // columns after a multi-column option stay aligned
+        let expected = Linked {
+            id: 1,
+            plain: Some((0, 9)),
+            link: None,
+            next: Some(0),
+        };
+        assert_eq!(rows, [expected]);
+    }
+
+    #[test]
+    fn named_rows() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Partial {
+            id: u32,
+            #[serde(rename = "Name")]
+            name: String,
+            #[serde(rename = "col_2")]
+            models: [u8; 2],
+        }
+
+        let exh = exh_fixture(1, 8, &[(0x0, 0), (0x3, 4), (0x3, 5), (0x3, 6)], 1);
+        let schema = SheetSchema {
+            columns: vec!["Name".into(), "Level".into()],
+            ..Default::default()
+        };
+        let mut row = vec![0, 0, 0, 0, 50, 1, 2, 0];
+        row.extend(b"Sword\0\0\0");
+        let exd = exd_fixture(&[(9, 1, row)]);
+
+        let rows: Vec<Partial> =
+            read_exd_data_named(Rc::new(exh.clone()), exd.clone(), Some(&schema))
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(
+            rows,
+            [Partial {
+                id: 9,
+                name: "Sword".into(),
+                models: [1, 2],
+            }]
+        );
+
+        let rows: Vec<HashMap<String, Value>> =
+            read_exd_data_named(Rc::new(exh), exd, Some(&schema))
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(rows[0]["Level"], Value::UInt8(50));
+        assert_eq!(rows[0]["col_3"], Value::UInt8(2));
+        assert_eq!(rows[0].len(), 5);
+    }
+
+    #[test]
+    fn value_accessors() {
+        assert_eq!(Value::Int8(-1).as_i64(), Some(-1));
+        assert_eq!(Value::Int8(-1).as_u64(), None);
+        assert_eq!(Value::UInt64(u64::MAX).as_i64(), None);
+        assert_eq!(Value::UInt16(7).as_u32(), Some(7));
+        assert_eq!(Value::UInt16(7).as_f64(), Some(7.0));
+        assert_eq!(Value::UInt64(u64::MAX - 1).as_f64(), None);
+        assert_eq!(Value::Float(0.5).as_i64(), None);
+        assert_eq!(Value::Bool(true).as_bool(), Some(true));
+        assert_eq!(Value::String("a".into()).as_str(), Some("a"));
+
+        assert_eq!(u8::try_from(&Value::Int32(255)).unwrap(), 255);
+        assert!(u8::try_from(&Value::Int32(256)).is_err());
+        assert!(f32::try_from(Value::UInt32(16_777_217)).is_err());
+        assert_eq!(Value::from(3u16), Value::UInt16(3));
+        assert_eq!(
+            Value::Int64(300).convert(ValueType::UInt16).unwrap(),
+            Value::UInt16(300)
+        );
+        assert!(Value::Int64(300).convert(ValueType::String).is_err());
+    }
+
+    #[test]
+    fn value_display_and_ordering() {
+        assert_eq!(Value::Int16(-3).to_string(), "-3");
+        assert_eq!(Value::Bool(false).to_string(), "false");
+        let markup = Value::parse(ValueType::String, "a<br>b").unwrap();
+        assert_eq!(markup.to_string(), "a<br>b");
+        assert_eq!(markup.to_markup(), "a<br>b");
+        let plain = Value::String("a<br>b\\".into());
+        assert_eq!(plain.to_string(), "a<br>b\\");
+        assert_eq!(plain.to_markup(), "a\\<br>b\\\\");
+        assert_eq!(
+            Value::parse(ValueType::String, &plain.to_markup()).unwrap(),
+            plain
+        );
+
+        use std::cmp::Ordering;
+        assert_ne!(Value::UInt8(1), Value::Int64(1));
+        assert_eq!(
+            Value::UInt8(1).cmp_loose(&Value::Int64(1)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            Value::Float(2.0).cmp_loose(&Value::UInt32(2)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            Value::Int8(-1).cmp_loose(&Value::UInt64(0)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Value::Float(0.5).cmp_loose(&Value::Int32(0)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Value::String("a".into()).cmp_loose(&Value::String("b".into())),
+            Some(Ordering::Less)
+        );
+        // by text rather than markup, where `<` would sort after `;`
+        assert_eq!(
+            markup.cmp_loose(&Value::String("a;".into())),
+            Some(Ordering::Less)
+        );
+        assert_eq!(Value::Bool(true).cmp_loose(&Value::UInt8(1)), None);
+        assert_eq!(Value::String("1".into()).cmp_loose(&Value::UInt8(1)), None);
+    }
+
+    #[test]
+    fn value_seed() {
+        use serde::de::{value::StrDeserializer, value::U64Deserializer, DeserializeSeed};
+        type E = de::value::Error;
+
+        let from_str = |vtype, text| ValueSeed(vtype).deserialize(StrDeserializer::<E>::new(text));
+        assert_eq!(from_str(ValueType::UInt8, "12").unwrap(), Value::UInt8(12));
+        assert!(from_str(ValueType::UInt8, "-1").is_err());
+        assert_eq!(
+            from_str(ValueType::String, "12").unwrap(),
+            Value::String("12".into())
+        );
+
+        let value = ValueSeed(ValueType::Int16)
+            .deserialize(U64Deserializer::<E>::new(300))
+            .unwrap();
+        assert_eq!(value, Value::Int16(300));
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(
+            Value::parse(ValueType::Int8, "-5").unwrap(),
+            Value::Int8(-5)
+        );
+        assert_eq!(
+            Value::parse(ValueType::Float32, "0.5").unwrap(),
+            Value::Float(0.5)
+        );
+        assert_eq!(
+            Value::parse(ValueType::PackedBool3, "true").unwrap(),
+            Value::Bool(true)
+        );
+        assert!(Value::parse(ValueType::UInt8, "256").is_err());
+        assert!(Value::parse(ValueType::Bool, "yes").is_err());
+
+        let plain = Value::parse(ValueType::String, "a\\\\b \\<x").unwrap();
+        assert_eq!(plain, Value::String("a\\b <x".into()));
+        assert!(Value::parse(ValueType::String, "a <x").is_err());
+        let markup = Value::parse(ValueType::String, "a<br>b").unwrap();
+        assert!(matches!(markup, Value::SeString(s) if s.to_markup() == "a<br>b"));
+    }
 }
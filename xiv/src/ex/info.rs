@@ -0,0 +1,154 @@
+use super::{read_exh, ExVariant, Exh, Locale};
+use crate::{error::XivError, sqpack::SqPack};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Summary of a sheet's .exh header, meant for inspection and for tracking
+/// sheet metadata across patches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SheetInfo {
+    pub name: Box<str>,
+    pub variant: ExVariant,
+    pub row_count: u32,
+    /// Size of fixed size column data of each (sub)row.
+    pub data_offset: u16,
+    pub pages: Vec<PageInfo>,
+    pub languages: Vec<Locale>,
+    pub columns: Vec<ColumnInfo>,
+    pub unknown: ExhUnknowns,
+}
+
+/// Range of row ids stored in one .exd page file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageInfo {
+    pub start_id: u32,
+    /// Start of the next page, `None` for the last one.
+    pub end_id: Option<u32>,
+    pub row_count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnInfo {
+    pub index: usize,
+    /// Type tag as used by exported .csv files (`str`, `u32`, `bool`, ...).
+    pub vtype: &'static str,
+    /// Bit within the byte at `offset` for packed bool columns.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bit: Option<u8>,
+    pub offset: u16,
+}
+
+/// Header fields with unknown meaning, kept to spot when they change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExhUnknowns {
+    pub unk0: u16,
+    pub unk1: u16,
+    pub u2: u8,
+    pub unk2: u16,
+    pub unk3: u32,
+    pub unk4: u32,
+}
+
+impl SheetInfo {
+    pub fn new(name: &str, exh: &Exh) -> Self {
+        let mut starts: Vec<u32> = exh.pages.iter().map(|p| p.start_id).collect();
+        starts.sort_unstable();
+        let pages = exh
+            .pages
+            .iter()
+            .map(|page| PageInfo {
+                start_id: page.start_id,
+                end_id: starts.iter().copied().find(|s| *s > page.start_id),
+                row_count: page.row_count,
+            })
+            .collect();
+
+        let columns = exh
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| ColumnInfo {
+                index,
+                vtype: column.vtype.type_tag(),
+                bit: column.vtype.packed_bit(),
+                offset: column.offset,
+            })
+            .collect();
+
+        Self {
+            name: name.into(),
+            variant: exh.variant,
+            row_count: exh.row_count,
+            data_offset: exh.data_offset,
+            pages,
+            languages: exh.languages.clone(),
+            columns,
+            unknown: ExhUnknowns {
+                unk0: exh.unk0,
+                unk1: exh.unk1,
+                u2: exh.u2,
+                unk2: exh.unk2,
+                unk3: exh.unk3,
+                unk4: exh.unk4,
+            },
+        }
+    }
+
+    /// Reads header of a sheet from SqPack repository and summarizes it.
+    pub fn read(repo: Arc<SqPack>, base_path: &str) -> Result<Self, XivError> {
+        Ok(Self::new(base_path, &read_exh(repo, base_path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ex::{ExColumn, ExPage, ValueType};
+
+    #[test]
+    fn sheet_info() {
+        let exh = Exh {
+            unk0: 3,
+            data_offset: 8,
+            column_count: 2,
+            page_count: 2,
+            language_count: 2,
+            unk1: 1,
+            u2: 0,
+            variant: ExVariant::SubRows,
+            unk2: 0,
+            row_count: 7,
+            unk3: 0,
+            unk4: 5,
+            columns: vec![
+                ExColumn {
+                    vtype: ValueType::String,
+                    offset: 0,
+                },
+                ExColumn {
+                    vtype: ValueType::PackedBool3,
+                    offset: 4,
+                },
+            ],
+            pages: vec![
+                ExPage {
+                    start_id: 0,
+                    row_count: 5,
+                },
+                ExPage {
+                    start_id: 500,
+                    row_count: 2,
+                },
+            ],
+            languages: vec![Locale::Japanese, Locale::English],
+        };
+
+        let info = SheetInfo::new("Sample", &exh);
+        assert_eq!(info.pages[0].end_id, Some(500));
+        assert_eq!(info.pages[1].end_id, None);
+        assert_eq!(info.columns[1].vtype, "bool");
+        assert_eq!(info.columns[1].bit, Some(3));
+        assert_eq!(info.columns[0].bit, None);
+        assert_eq!(info.unknown.unk4, 5);
+    }
+}
@@ -0,0 +1,437 @@
+use super::{read_exh, ExColumn, ExVariant, Exh, ValueType};
+use crate::{error::XivError, sqpack::SqPack};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// Column layout of a sheet, i.e. what typed row structs depend on.
+///
+/// Its text form lists columns as `type@offset` (`bool.N` for packed bools),
+/// prefixed by `subrows:` for `ExVariant::SubRows` sheets, e.g.
+/// `str@0,u32@4,bool.3@8`. It is meant to be stored next to a typed struct
+/// to detect when a patch changes the sheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExhLayout {
+    pub variant: ExVariant,
+    pub columns: Vec<ExColumn>,
+}
+
+/// Difference between expected and actual column of a sheet. Columns are
+/// referred to by their index within the layout they are found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnDiff {
+    Variant {
+        expected: ExVariant,
+        actual: ExVariant,
+    },
+    Changed {
+        actual_index: usize,
+        expected: ExColumn,
+        actual: ExColumn,
+    },
+    Added {
+        actual_index: usize,
+        actual: ExColumn,
+    },
+    Removed {
+        expected_index: usize,
+        expected: ExColumn,
+    },
+}
+
+impl fmt::Display for ColumnDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Variant { expected, actual } => {
+                write!(f, "variant changed from {expected:?} to {actual:?}")
+            }
+            Self::Changed {
+                actual_index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column {actual_index} changed from {expected} to {actual}"
+            ),
+            Self::Added {
+                actual_index,
+                actual,
+            } => write!(f, "column {actual_index} {actual} added"),
+            Self::Removed {
+                expected_index,
+                expected,
+            } => write!(f, "expected column {expected_index} {expected} removed"),
+        }
+    }
+}
+
+pub(crate) fn join_diffs(diffs: &[ColumnDiff]) -> String {
+    diffs
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl Exh {
+    pub fn layout(&self) -> ExhLayout {
+        ExhLayout {
+            variant: self.variant,
+            columns: self.columns.clone(),
+        }
+    }
+}
+
+impl ExhLayout {
+    /// Short hash of the layout, CRC-32 of its text form.
+    pub fn fingerprint(&self) -> u32 {
+        Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(self.to_string().as_bytes())
+    }
+
+    /// Lists columns of `actual` layout which differ from this one.
+    ///
+    /// Columns are aligned by the longest common subsequence of their types,
+    /// so a column inserted or removed in the middle is reported alone rather
+    /// than as a change of every column after it. Aligned columns whose offset
+    /// has moved are reported as changed, as are unmatched columns between
+    /// aligned ones pairwise; the remaining ones are added or removed.
+    pub fn diff(&self, actual: &ExhLayout) -> Vec<ColumnDiff> {
+        let mut diffs = Vec::new();
+        if self.variant != actual.variant {
+            diffs.push(ColumnDiff::Variant {
+                expected: self.variant,
+                actual: actual.variant,
+            });
+        }
+
+        let (expected, actual) = (&self.columns, &actual.columns);
+        // lcs[i][j]: length of common subsequence of expected[i..] and actual[j..]
+        let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+        for i in (0..expected.len()).rev() {
+            for j in (0..actual.len()).rev() {
+                lcs[i][j] = match expected[i].vtype == actual[j].vtype {
+                    true => lcs[i + 1][j + 1] + 1,
+                    false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        loop {
+            let matched =
+                i < expected.len() && j < actual.len() && expected[i].vtype == actual[j].vtype;
+            if matched || (i == expected.len() && j == actual.len()) {
+                push_unmatched(&mut diffs, expected, actual, &removed, &added);
+                removed.clear();
+                added.clear();
+                if !matched {
+                    break;
+                }
+                if expected[i].offset != actual[j].offset {
+                    diffs.push(ColumnDiff::Changed {
+                        actual_index: j,
+                        expected: expected[i],
+                        actual: actual[j],
+                    });
+                }
+                (i, j) = (i + 1, j + 1);
+            } else if j < actual.len() && (i == expected.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                added.push(j);
+                j += 1;
+            } else {
+                removed.push(i);
+                i += 1;
+            }
+        }
+        diffs
+    }
+}
+
+/// Reports columns left unmatched between two aligned ones.
+fn push_unmatched(
+    diffs: &mut Vec<ColumnDiff>,
+    expected: &[ExColumn],
+    actual: &[ExColumn],
+    removed: &[usize],
+    added: &[usize],
+) {
+    for (i, j) in removed.iter().zip(added) {
+        diffs.push(ColumnDiff::Changed {
+            actual_index: *j,
+            expected: expected[*i],
+            actual: actual[*j],
+        });
+    }
+    for i in removed.iter().skip(added.len()) {
+        diffs.push(ColumnDiff::Removed {
+            expected_index: *i,
+            expected: expected[*i],
+        });
+    }
+    for j in added.iter().skip(removed.len()) {
+        diffs.push(ColumnDiff::Added {
+            actual_index: *j,
+            actual: actual[*j],
+        });
+    }
+}
+
+impl fmt::Display for ExColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.vtype.packed_bit() {
+            Some(bit) => write!(f, "bool.{bit}@{}", self.offset),
+            None => write!(f, "{}@{}", self.vtype, self.offset),
+        }
+    }
+}
+
+impl FromStr for ExColumn {
+    type Err = XivError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || XivError::ExhLayout(s.into());
+        let (tag, offset) = s.trim().split_once('@').ok_or_else(err)?;
+        let vtype = match tag {
+            "str" => ValueType::String,
+            "bool" => ValueType::Bool,
+            "i8" => ValueType::Int8,
+            "u8" => ValueType::UInt8,
+            "i16" => ValueType::Int16,
+            "u16" => ValueType::UInt16,
+            "i32" => ValueType::Int32,
+            "u32" => ValueType::UInt32,
+            "f32" => ValueType::Float32,
+            "i64" => ValueType::Int64,
+            "u64" => ValueType::UInt64,
+            "bool.0" => ValueType::PackedBool0,
+            "bool.1" => ValueType::PackedBool1,
+            "bool.2" => ValueType::PackedBool2,
+            "bool.3" => ValueType::PackedBool3,
+            "bool.4" => ValueType::PackedBool4,
+            "bool.5" => ValueType::PackedBool5,
+            "bool.6" => ValueType::PackedBool6,
+            "bool.7" => ValueType::PackedBool7,
+            _ => return Err(err()),
+        };
+        Ok(Self {
+            vtype,
+            offset: offset.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+impl fmt::Display for ExhLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.variant == ExVariant::SubRows {
+            f.write_str("subrows:")?;
+        }
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{column}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ExhLayout {
+    type Err = XivError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (variant, columns) = match s.trim().strip_prefix("subrows:") {
+            Some(columns) => (ExVariant::SubRows, columns),
+            None => (ExVariant::Normal, s.trim()),
+        };
+        let columns = match columns.is_empty() {
+            true => Vec::new(),
+            false => columns
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Self { variant, columns })
+    }
+}
+
+impl Serialize for ExhLayout {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExhLayout {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Typed row struct of a sheet, along with the layout it was written for.
+pub trait SheetRow {
+    const SHEET: &'static str;
+    /// Expected layout in its text form, see `ExhLayout`.
+    const LAYOUT: &'static str;
+
+    /// Compares expected layout with the one of `exh`.
+    fn check_layout(exh: &Exh) -> Result<(), XivError> {
+        let expected: ExhLayout = Self::LAYOUT.parse()?;
+        let diffs = expected.diff(&exh.layout());
+        match diffs.is_empty() {
+            true => Ok(()),
+            false => Err(XivError::ExhLayoutMismatch(Self::SHEET.into(), diffs)),
+        }
+    }
+
+    /// Reads header of the sheet and compares its layout with the expected one.
+    fn check_sheet(repo: Arc<SqPack>) -> Result<(), XivError> {
+        Self::check_layout(&read_exh(repo, Self::SHEET)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(vtype: ValueType, offset: u16) -> ExColumn {
+        ExColumn { vtype, offset }
+    }
+
+    #[test]
+    fn layout_text_form() {
+        let layout = ExhLayout {
+            variant: ExVariant::SubRows,
+            columns: vec![
+                column(ValueType::String, 0),
+                column(ValueType::UInt32, 4),
+                column(ValueType::PackedBool3, 8),
+            ],
+        };
+        let text = layout.to_string();
+        assert_eq!(text, "subrows:str@0,u32@4,bool.3@8");
+        assert_eq!(text.parse::<ExhLayout>().unwrap(), layout);
+        assert_ne!(
+            layout.fingerprint(),
+            "str@0,u32@4,bool.3@8"
+                .parse::<ExhLayout>()
+                .unwrap()
+                .fingerprint()
+        );
+        assert!("str@x".parse::<ExhLayout>().is_err());
+        assert!("".parse::<ExhLayout>().unwrap().columns.is_empty());
+    }
+
+    #[test]
+    fn sheet_row_layout() {
+        struct Sample;
+
+        impl SheetRow for Sample {
+            const SHEET: &'static str = "Sample";
+            const LAYOUT: &'static str = "u8@0,u8@1";
+        }
+
+        let mut exh = Exh {
+            unk0: 3,
+            data_offset: 4,
+            column_count: 2,
+            page_count: 0,
+            language_count: 0,
+            unk1: 0,
+            u2: 0,
+            variant: ExVariant::Normal,
+            unk2: 0,
+            row_count: 0,
+            unk3: 0,
+            unk4: 0,
+            columns: vec![column(ValueType::UInt8, 0), column(ValueType::UInt8, 1)],
+            pages: Vec::new(),
+            languages: Vec::new(),
+        };
+        assert!(Sample::check_layout(&exh).is_ok());
+
+        exh.columns[1].offset = 2;
+        let err = Sample::check_layout(&exh).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column layout of sheet Sample has changed: column 1 changed from u8@1 to u8@2"
+        );
+    }
+
+    #[test]
+    fn layout_diff() {
+        let expected: ExhLayout = "str@0,u32@4,u8@8".parse().unwrap();
+        let actual: ExhLayout = "str@0,i32@4,u8@8,u16@10".parse().unwrap();
+        assert_eq!(
+            expected.diff(&actual),
+            [
+                ColumnDiff::Changed {
+                    actual_index: 1,
+                    expected: column(ValueType::UInt32, 4),
+                    actual: column(ValueType::Int32, 4),
+                },
+                ColumnDiff::Added {
+                    actual_index: 3,
+                    actual: column(ValueType::UInt16, 10),
+                },
+            ]
+        );
+        assert_eq!(
+            actual.diff(&expected)[1].to_string(),
+            "expected column 3 u16@10 removed"
+        );
+        assert!(expected.diff(&expected).is_empty());
+    }
+
+    #[test]
+    fn layout_diff_insertion() {
+        let expected: ExhLayout = "str@0,u32@4,u8@8,bool.0@9".parse().unwrap();
+        let actual: ExhLayout = "str@0,i16@10,u32@4,u8@8,bool.0@9".parse().unwrap();
+        assert_eq!(
+            expected.diff(&actual),
+            [ColumnDiff::Added {
+                actual_index: 1,
+                actual: column(ValueType::Int16, 10),
+            }]
+        );
+        assert_eq!(
+            actual.diff(&expected),
+            [ColumnDiff::Removed {
+                expected_index: 1,
+                expected: column(ValueType::Int16, 10),
+            }]
+        );
+
+        let changed: ExhLayout = "str@0,i16@10,u32@4,i8@8".parse().unwrap();
+        let diffs: Vec<_> = expected
+            .diff(&changed)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            diffs,
+            [
+                "column 1 i16@10 added",
+                "column 3 changed from u8@8 to i8@8",
+                "expected column 3 bool.0@9 removed",
+            ]
+        );
+
+        // inserted column shifts offsets of the following ones
+        let shifted: ExhLayout = "str@0,i16@4,u32@6,u8@10,bool.0@11".parse().unwrap();
+        let diffs: Vec<_> = expected
+            .diff(&shifted)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            diffs,
+            [
+                "column 1 i16@4 added",
+                "column 2 changed from u32@4 to u32@6",
+                "column 3 changed from u8@8 to u8@10",
+                "column 4 changed from bool.0@9 to bool.0@11",
+            ]
+        );
+    }
+}
@@ -0,0 +1,596 @@
+use super::{read_exd, read_exh, schema::SheetSchema, ExVariant, Exh, Locale, Row, Value};
+use crate::{error::XivError, sqpack::SqPack};
+use std::{
+    cmp::Ordering, collections::HashMap, iter::Peekable, str::CharIndices, str::FromStr, sync::Arc,
+};
+
+/// Column of a `Table`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// Name to display, e.g. `Name` or `ItemUICategory.col_0`.
+    pub name: Box<str>,
+    /// Every name the column can be referenced by, e.g. `Name`, `col_9`, `#9`.
+    pub aliases: Vec<Box<str>>,
+}
+
+impl Column {
+    fn new(aliases: Vec<Box<str>>) -> Self {
+        Self {
+            name: aliases[0].clone(),
+            aliases,
+        }
+    }
+
+    fn prefixed(&self, prefix: &str) -> Self {
+        Self::new(
+            self.aliases
+                .iter()
+                .map(|a| format!("{prefix}.{a}").into())
+                .collect(),
+        )
+    }
+}
+
+/// Rows of a sheet (or a query result) along with names of their columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub columns: Vec<Column>,
+    pub rows: Vec<Row>,
+}
+
+impl Table {
+    /// Names columns of sheet rows: `id`, `subid` for `ExVariant::SubRows`
+    /// sheets, then every column by its schema name, `col_N` and `#N`.
+    pub fn new(exh: &Exh, schema: Option<&SheetSchema>, rows: Vec<Row>) -> Self {
+        let mut columns = vec![Column::new(vec!["id".into()])];
+        if exh.variant == ExVariant::SubRows {
+            columns.push(Column::new(vec!["subid".into()]));
+        }
+        for idx in 0..exh.columns.len() {
+            let mut aliases: Vec<Box<str>> =
+                vec![format!("col_{idx}").into(), format!("#{idx}").into()];
+            if let Some(name) = schema.map(|s| s.column_name(idx)) {
+                if *name != *aliases[0] {
+                    aliases.insert(0, name.into());
+                }
+            }
+            columns.push(Column::new(aliases));
+        }
+        Self { columns, rows }
+    }
+
+    /// Reads every row of a sheet.
+    pub fn read(
+        repo: Arc<SqPack>,
+        sheet_name: &str,
+        locale: Locale,
+        schema: Option<&SheetSchema>,
+    ) -> Result<Self, XivError> {
+        let exh = read_exh(repo.clone(), sheet_name)?;
+        let rows = read_exd(repo, sheet_name, locale)?.collect::<Result<_, _>>()?;
+        Ok(Self::new(&exh, schema, rows))
+    }
+
+    /// Index of column by any of its names.
+    pub fn column_index(&self, reference: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.aliases.iter().any(|a| a.as_ref() == reference))
+    }
+
+    fn require_column(&self, reference: &str) -> Result<usize, XivError> {
+        self.column_index(reference)
+            .ok_or_else(|| XivError::QueryColumn(reference.into()))
+    }
+}
+
+/// Joins rows of another sheet whose id is stored in `column`. Its columns
+/// become available as `<column>.<name>`.
+///
+/// Rows with no matching row in the other sheet are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Join {
+    pub column: Box<str>,
+    pub sheet: Box<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// Filter, join, projection and sort over rows of a sheet.
+///
+/// ```ignore
+/// let query = Query {
+///     filter: Some("LevelItem > 600 and ItemUICategory = 34".parse()?),
+///     select: vec!["id".into(), "Name".into()],
+///     ..Default::default()
+/// };
+/// let result = query.run(Table::read(repo, "Item", Locale::English, schema)?, load_sheet)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub joins: Vec<Join>,
+    pub filter: Option<Filter>,
+    /// Columns to keep, all of them if empty.
+    pub select: Vec<Box<str>>,
+    pub order_by: Vec<(Box<str>, Order)>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Runs the query over `table`, loading joined sheets by their name with `load`.
+    pub fn run<F>(&self, mut table: Table, mut load: F) -> Result<Table, XivError>
+    where
+        F: FnMut(&str) -> Result<Table, XivError>,
+    {
+        for join in &self.joins {
+            let other = load(&join.sheet)?;
+            table = join_tables(table, &join.column, other)?;
+        }
+
+        if let Some(filter) = &self.filter {
+            let filter = filter.resolve(&table)?;
+            table.rows.retain(|row| filter.is_true(row));
+        }
+
+        let order_by = self
+            .order_by
+            .iter()
+            .map(|(c, o)| Ok((table.require_column(c)?, *o)))
+            .collect::<Result<Vec<_>, XivError>>()?;
+        if !order_by.is_empty() {
+            table.rows.sort_by(|a, b| {
+                order_by
+                    .iter()
+                    .map(|(idx, order)| {
+                        let ord = a[*idx].cmp_loose(&b[*idx]).unwrap_or(Ordering::Equal);
+                        match order {
+                            Order::Asc => ord,
+                            Order::Desc => ord.reverse(),
+                        }
+                    })
+                    .find(|ord| ord.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        if let Some(limit) = self.limit {
+            table.rows.truncate(limit);
+        }
+
+        if !self.select.is_empty() {
+            let indices = self
+                .select
+                .iter()
+                .map(|c| table.require_column(c))
+                .collect::<Result<Vec<_>, _>>()?;
+            table = Table {
+                columns: indices.iter().map(|i| table.columns[*i].clone()).collect(),
+                rows: table
+                    .rows
+                    .iter()
+                    .map(|row| indices.iter().map(|i| row[*i].clone()).collect())
+                    .collect(),
+            };
+        }
+        Ok(table)
+    }
+}
+
+fn join_tables(table: Table, column: &str, other: Table) -> Result<Table, XivError> {
+    let link_idx = table.require_column(column)?;
+
+    let mut by_id: HashMap<u32, Vec<&Row>> = HashMap::new();
+    for row in &other.rows {
+        if let Some(id) = row.first().and_then(Value::as_u32) {
+            by_id.entry(id).or_default().push(row);
+        }
+    }
+
+    let mut rows = Vec::with_capacity(table.rows.len());
+    for row in &table.rows {
+        let id = row[link_idx]
+            .as_u32()
+            .ok_or_else(|| XivError::QueryLink(column.into()))?;
+        for linked in by_id.get(&id).into_iter().flatten() {
+            rows.push(row.iter().chain(linked.iter()).cloned().collect());
+        }
+    }
+
+    let mut columns = table.columns;
+    columns.extend(other.columns.iter().map(|c| c.prefixed(column)));
+    Ok(Table { columns, rows })
+}
+
+/// Text of string values, plain for ones with macros.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(v) => Some(v.to_string()),
+        Value::SeString(v) => Some(v.to_plain_text()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    /// Case-insensitive substring match.
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand<C> {
+    Column(C),
+    Literal(Value),
+}
+
+/// Boolean expression over columns of a row, e.g.
+/// `LevelItem >= 600 and (ItemUICategory = 34 or Name ~ 'ring')`.
+///
+/// Columns are referenced by any of their names in `Table`. Bare operands
+/// are true when non-zero or non-empty.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter<C = Box<str>> {
+    Operand(Operand<C>),
+    Cmp(CmpOp, Operand<C>, Operand<C>),
+    Not(Box<Filter<C>>),
+    And(Box<Filter<C>>, Box<Filter<C>>),
+    Or(Box<Filter<C>>, Box<Filter<C>>),
+}
+
+impl Filter {
+    fn resolve(&self, table: &Table) -> Result<Filter<usize>, XivError> {
+        let operand = |o: &Operand<Box<str>>| -> Result<Operand<usize>, XivError> {
+            Ok(match o {
+                Operand::Column(c) => Operand::Column(table.require_column(c)?),
+                Operand::Literal(v) => Operand::Literal(v.clone()),
+            })
+        };
+        Ok(match self {
+            Self::Operand(o) => Filter::Operand(operand(o)?),
+            Self::Cmp(op, a, b) => Filter::Cmp(*op, operand(a)?, operand(b)?),
+            Self::Not(f) => Filter::Not(Box::new(f.resolve(table)?)),
+            Self::And(a, b) => {
+                Filter::And(Box::new(a.resolve(table)?), Box::new(b.resolve(table)?))
+            }
+            Self::Or(a, b) => Filter::Or(Box::new(a.resolve(table)?), Box::new(b.resolve(table)?)),
+        })
+    }
+}
+
+impl Filter<usize> {
+    fn is_true(&self, row: &Row) -> bool {
+        fn value<'a>(row: &'a Row, o: &'a Operand<usize>) -> &'a Value {
+            match o {
+                Operand::Column(idx) => &row[*idx],
+                Operand::Literal(v) => v,
+            }
+        }
+
+        match self {
+            Self::Operand(o) => {
+                let v = value(row, o);
+                match (v.as_bool(), text(v)) {
+                    (Some(b), _) => b,
+                    (_, Some(t)) => !t.is_empty(),
+                    _ => v.cmp_loose(&Value::UInt8(0)) != Some(Ordering::Equal),
+                }
+            }
+            Self::Cmp(CmpOp::Contains, a, b) => match (text(value(row, a)), text(value(row, b))) {
+                (Some(a), Some(b)) => a.to_lowercase().contains(&b.to_lowercase()),
+                _ => false,
+            },
+            Self::Cmp(op, a, b) => match value(row, a).cmp_loose(value(row, b)) {
+                Some(ord) => match op {
+                    CmpOp::Equal => ord.is_eq(),
+                    CmpOp::NotEqual => ord.is_ne(),
+                    CmpOp::Less => ord.is_lt(),
+                    CmpOp::LessOrEqual => ord.is_le(),
+                    CmpOp::Greater => ord.is_gt(),
+                    CmpOp::GreaterOrEqual => ord.is_ge(),
+                    CmpOp::Contains => unreachable!(),
+                },
+                None => *op == CmpOp::NotEqual,
+            },
+            Self::Not(f) => !f.is_true(row),
+            Self::And(a, b) => a.is_true(row) && b.is_true(row),
+            Self::Or(a, b) => a.is_true(row) || b.is_true(row),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = XivError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = FilterParser {
+            chars: s.char_indices().peekable(),
+            src: s,
+        };
+        let filter = parser.or()?;
+        parser.skip_whitespace();
+        match parser.chars.peek() {
+            None => Ok(filter),
+            Some(_) => Err(parser.error()),
+        }
+    }
+}
+
+struct FilterParser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    src: &'a str,
+}
+
+impl FilterParser<'_> {
+    fn error(&mut self) -> XivError {
+        let pos = self.chars.peek().map_or(self.src.len(), |(i, _)| *i);
+        XivError::QuerySyntax(pos)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.chars.next();
+        }
+    }
+
+    fn is_word_char(c: char) -> bool {
+        c.is_alphanumeric() || matches!(c, '_' | '.' | '#' | '[' | ']' | '-')
+    }
+
+    /// Consumes `keyword` (case-insensitive) or one of `symbols`.
+    fn keyword(&mut self, keyword: &str, symbols: &[&str]) -> bool {
+        self.skip_whitespace();
+        let rest = &self.src[self.chars.peek().map_or(self.src.len(), |(i, _)| *i)..];
+        let len = if rest
+            .get(..keyword.len())
+            .is_some_and(|w| w.eq_ignore_ascii_case(keyword))
+            && !rest[keyword.len()..].starts_with(Self::is_word_char)
+        {
+            keyword.len()
+        } else if let Some(symbol) = symbols.iter().find(|s| rest.starts_with(**s)) {
+            symbol.len()
+        } else {
+            return false;
+        };
+        for _ in rest[..len].chars() {
+            self.chars.next();
+        }
+        true
+    }
+
+    fn or(&mut self) -> Result<Filter, XivError> {
+        let mut lhs = self.and()?;
+        while self.keyword("or", &["||"]) {
+            lhs = Filter::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Filter, XivError> {
+        let mut lhs = self.not()?;
+        while self.keyword("and", &["&&"]) {
+            lhs = Filter::And(Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Filter, XivError> {
+        if self.keyword("not", &["!"]) {
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> Result<Filter, XivError> {
+        self.skip_whitespace();
+        if self.peek() == Some('(') {
+            self.chars.next();
+            let filter = self.or()?;
+            self.skip_whitespace();
+            if self.peek() != Some(')') {
+                return Err(self.error());
+            }
+            self.chars.next();
+            return Ok(filter);
+        }
+
+        let lhs = self.operand()?;
+        self.skip_whitespace();
+        let op = match self.peek() {
+            Some('=') => CmpOp::Equal,
+            Some('!') => CmpOp::NotEqual,
+            Some('<') => CmpOp::Less,
+            Some('>') => CmpOp::Greater,
+            Some('~') => CmpOp::Contains,
+            _ => return Ok(Filter::Operand(lhs)),
+        };
+        self.chars.next();
+        let op = match (op, self.peek()) {
+            (CmpOp::Equal, Some('=')) => CmpOp::Equal,
+            (CmpOp::NotEqual, Some('=')) => CmpOp::NotEqual,
+            (CmpOp::Less, Some('=')) => CmpOp::LessOrEqual,
+            (CmpOp::Greater, Some('=')) => CmpOp::GreaterOrEqual,
+            (CmpOp::NotEqual, _) => return Err(self.error()),
+            (op, _) => {
+                let rhs = self.operand()?;
+                return Ok(Filter::Cmp(op, lhs, rhs));
+            }
+        };
+        self.chars.next();
+        let rhs = self.operand()?;
+        Ok(Filter::Cmp(op, lhs, rhs))
+    }
+
+    fn operand(&mut self) -> Result<Operand<Box<str>>, XivError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(quote @ ('\'' | '"')) => {
+                self.chars.next();
+                let mut text = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some((_, c)) if c == quote => break,
+                        Some((_, '\\')) => {
+                            text.push(self.chars.next().ok_or_else(|| self.error())?.1)
+                        }
+                        Some((_, c)) => text.push(c),
+                        None => return Err(self.error()),
+                    }
+                }
+                Ok(Operand::Literal(Value::String(text.into())))
+            }
+            Some(c) if Self::is_word_char(c) => {
+                let mut word = String::new();
+                while let Some(c) = self.peek().filter(|c| Self::is_word_char(*c)) {
+                    word.push(c);
+                    self.chars.next();
+                }
+                if let Ok(v) = word.parse::<i64>() {
+                    Ok(Operand::Literal(Value::Int64(v)))
+                } else if let Ok(v) = word.parse::<u64>() {
+                    Ok(Operand::Literal(Value::UInt64(v)))
+                } else if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                    let v = word.parse::<f32>().map_err(|_| self.error())?;
+                    Ok(Operand::Literal(Value::Float(v)))
+                } else if word.eq_ignore_ascii_case("true") || word.eq_ignore_ascii_case("false") {
+                    Ok(Operand::Literal(Value::Bool(
+                        word.eq_ignore_ascii_case("true"),
+                    )))
+                } else {
+                    Ok(Operand::Column(word.into()))
+                }
+            }
+            _ => Err(self.error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(columns: &[&str], rows: Vec<Row>) -> Table {
+        Table {
+            columns: columns
+                .iter()
+                .map(|c| Column::new(vec![(*c).into()]))
+                .collect(),
+            rows,
+        }
+    }
+
+    fn items() -> Table {
+        let row = |id, name: &str, level, category| {
+            vec![
+                Value::UInt32(id),
+                Value::String(name.into()),
+                Value::UInt16(level),
+                Value::UInt8(category),
+            ]
+        };
+        table(
+            &["id", "Name", "LevelItem", "ItemUICategory"],
+            vec![
+                row(1, "Gil", 1, 63),
+                row(2, "Platinum Ring", 620, 43),
+                row(3, "Iron Sword", 610, 34),
+                row(4, "Gold Sword", 640, 34),
+            ],
+        )
+    }
+
+    fn ids(table: &Table) -> Vec<u32> {
+        table.rows.iter().map(|r| r[0].as_u32().unwrap()).collect()
+    }
+
+    #[test]
+    fn parse_filter() {
+        let filter: Filter = "LevelItem > 600 and not (Name ~ 'ring' || #3 != 34)"
+            .parse()
+            .unwrap();
+        let Filter::And(lhs, rhs) = filter else {
+            panic!()
+        };
+        assert_eq!(
+            *lhs,
+            Filter::Cmp(
+                CmpOp::Greater,
+                Operand::Column("LevelItem".into()),
+                Operand::Literal(Value::Int64(600))
+            )
+        );
+        assert!(matches!(*rhs, Filter::Not(f) if matches!(*f, Filter::Or(..))));
+
+        assert!("LevelItem >".parse::<Filter>().is_err());
+        assert!("(a = 1".parse::<Filter>().is_err());
+        assert!("a = 'open".parse::<Filter>().is_err());
+        assert!("android or notable".parse::<Filter>().is_ok());
+    }
+
+    #[test]
+    fn filter_sort_select() {
+        let query = Query {
+            filter: Some("LevelItem > 600 and ItemUICategory = 34".parse().unwrap()),
+            order_by: vec![("LevelItem".into(), Order::Desc)],
+            select: vec!["id".into(), "Name".into()],
+            ..Default::default()
+        };
+        let result = query.run(items(), |_| unreachable!()).unwrap();
+        assert_eq!(ids(&result), [4, 3]);
+        assert_eq!(result.columns.len(), 2);
+        assert_eq!(result.rows[0][1], Value::String("Gold Sword".into()));
+
+        let query = Query {
+            filter: Some("Name ~ 'SWORD' and LevelItem >= 640.0".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&query.run(items(), |_| unreachable!()).unwrap()), [4]);
+
+        let query = Query {
+            filter: Some("Nope = 1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(query.run(items(), |_| unreachable!()).is_err());
+    }
+
+    #[test]
+    fn join_sheets() {
+        let categories = table(
+            &["id", "Name"],
+            vec![
+                vec![Value::UInt32(34), Value::String("Gladiator's Arm".into())],
+                vec![Value::UInt32(43), Value::String("Ring".into())],
+            ],
+        );
+        let query = Query {
+            joins: vec![Join {
+                column: "ItemUICategory".into(),
+                sheet: "ItemUICategory".into(),
+            }],
+            filter: Some("ItemUICategory.Name = 'Ring'".parse().unwrap()),
+            select: vec!["Name".into(), "ItemUICategory.Name".into()],
+            ..Default::default()
+        };
+        let result = query
+            .run(items(), |sheet| {
+                assert_eq!(sheet, "ItemUICategory");
+                Ok(categories.clone())
+            })
+            .unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.columns[1].name.as_ref(), "ItemUICategory.Name");
+        assert_eq!(result.rows[0][1], Value::String("Ring".into()));
+    }
+}
@@ -0,0 +1,400 @@
+use super::{ExVariant, ExdHeader, ExdLocation, Exh, Row, Value, ValueType};
+use crate::{error::XivError, sestring::SeString};
+use binrw::BinRead;
+use serde::{ser, Serialize, Serializer};
+use std::{fmt, io::Cursor};
+
+/// Byte starting every SeString macro.
+const PAYLOAD_START: u8 = 0x02;
+
+/// Column value borrowed from .exd page data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueRef<'a> {
+    Bool(bool),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
+    UInt64(u64),
+    Float(f32),
+    /// Raw SeString bytes, without the trailing NUL.
+    Str(&'a [u8]),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Text of strings without any macros.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self {
+            Self::Str(raw) if !raw.contains(&PAYLOAD_START) => std::str::from_utf8(raw).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Result<Value, XivError> {
+        Ok(match *self {
+            Self::Bool(v) => Value::Bool(v),
+            Self::Int8(v) => Value::Int8(v),
+            Self::Int16(v) => Value::Int16(v),
+            Self::Int32(v) => Value::Int32(v),
+            Self::Int64(v) => Value::Int64(v),
+            Self::UInt8(v) => Value::UInt8(v),
+            Self::UInt16(v) => Value::UInt16(v),
+            Self::UInt32(v) => Value::UInt32(v),
+            Self::UInt64(v) => Value::UInt64(v),
+            Self::Float(v) => Value::Float(v),
+            Self::Str(raw) => match self.as_str() {
+                Some(text) => Value::String(text.into()),
+                None => {
+                    let s = SeString::parse(raw)?;
+                    match s.is_plain() {
+                        true => Value::String(s.to_plain_text().into()),
+                        false => Value::SeString(s),
+                    }
+                }
+            },
+        })
+    }
+
+    /// Same text form as `Value::to_markup` gives.
+    pub fn to_markup(&self) -> Result<String, XivError> {
+        match self.as_str() {
+            Some(text) => Ok(SeString::from(text).to_markup()),
+            None => Ok(self.to_value()?.to_markup()),
+        }
+    }
+}
+
+/// Same text form as `Value` has.
+impl fmt::Display for ValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_str() {
+            Some(text) => text.fmt(f),
+            None => self.to_value().map_err(|_| fmt::Error)?.fmt(f),
+        }
+    }
+}
+
+impl Serialize for ValueRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Self::Bool(v) => serializer.serialize_bool(v),
+            Self::Int8(v) => serializer.serialize_i8(v),
+            Self::Int16(v) => serializer.serialize_i16(v),
+            Self::Int32(v) => serializer.serialize_i32(v),
+            Self::Int64(v) => serializer.serialize_i64(v),
+            Self::UInt8(v) => serializer.serialize_u8(v),
+            Self::UInt16(v) => serializer.serialize_u16(v),
+            Self::UInt32(v) => serializer.serialize_u32(v),
+            Self::UInt64(v) => serializer.serialize_u64(v),
+            Self::Float(v) => serializer.serialize_f32(v),
+            Self::Str(_) => match self.as_str() {
+                Some(text) => serializer.serialize_str(text),
+                None => self
+                    .to_value()
+                    .map_err(ser::Error::custom)?
+                    .serialize(serializer),
+            },
+        }
+    }
+}
+
+/// Row of an .exd page borrowed from its data, decoding columns on demand.
+#[derive(Debug, Clone, Copy)]
+pub struct RowRef<'a> {
+    exh: &'a Exh,
+    data: &'a [u8],
+    /// Location of the page, to report errors at.
+    location: &'a ExdLocation,
+    id: u32,
+    subid: Option<u16>,
+    /// Start of fixed size column data of the (sub)row.
+    offset: usize,
+}
+
+impl<'a> RowRef<'a> {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Subrow id for `ExVariant::SubRows` sheets.
+    pub fn subid(&self) -> Option<u16> {
+        self.subid
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.exh.columns.len()
+    }
+
+    fn error(&self, column: usize, msg: &str) -> XivError {
+        let location = ExdLocation {
+            row_id: Some(self.id),
+            subrow_id: self.subid,
+            column: Some(column),
+            vtype: self.exh.columns.get(column).map(|c| c.vtype),
+            ..self.location.clone()
+        };
+        XivError::ExdDeserialization(Box::new(location), msg.into())
+    }
+
+    fn bytes<const N: usize>(&self, column: usize, offset: usize) -> Result<[u8; N], XivError> {
+        self.data
+            .get(offset..offset + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| self.error(column, "column data is out of bounds"))
+    }
+
+    /// Decodes column by its index within `Exh::columns`.
+    pub fn get(&self, column: usize) -> Result<ValueRef<'a>, XivError> {
+        let c = self
+            .exh
+            .columns
+            .get(column)
+            .ok_or_else(|| self.error(column, "no such column"))?;
+        let offset = self.offset + c.offset as usize;
+
+        Ok(match c.vtype {
+            ValueType::String => {
+                let str_offset = u32::from_be_bytes(self.bytes(column, offset)?) as usize;
+                let start = self.offset + self.exh.data_offset as usize + str_offset;
+                let rest = self.data.get(start..).unwrap_or_default();
+                let len = rest
+                    .iter()
+                    .position(|b| *b == 0)
+                    .ok_or_else(|| self.error(column, "string is out of bounds"))?;
+                ValueRef::Str(&rest[..len])
+            }
+            ValueType::Bool => ValueRef::Bool(self.bytes::<1>(column, offset)?[0] != 0),
+            ValueType::Int8 => ValueRef::Int8(i8::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::UInt8 => ValueRef::UInt8(self.bytes::<1>(column, offset)?[0]),
+            ValueType::Int16 => ValueRef::Int16(i16::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::UInt16 => ValueRef::UInt16(u16::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::Int32 => ValueRef::Int32(i32::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::UInt32 => ValueRef::UInt32(u32::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::Int64 => ValueRef::Int64(i64::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::UInt64 => ValueRef::UInt64(u64::from_be_bytes(self.bytes(column, offset)?)),
+            ValueType::Float32 => ValueRef::Float(f32::from_be_bytes(self.bytes(column, offset)?)),
+            packed => {
+                let mask = packed.packed_bit_mask().unwrap();
+                ValueRef::Bool(self.bytes::<1>(column, offset)?[0] & mask != 0)
+            }
+        })
+    }
+
+    /// Decodes every column in order.
+    pub fn values(&self) -> impl Iterator<Item = Result<ValueRef<'a>, XivError>> + 'a {
+        let row = *self;
+        (0..row.column_count()).map(move |column| row.get(column))
+    }
+
+    /// Decodes row into `Row` with its keys, as `read_exd` does.
+    pub fn to_row(&self) -> Result<Row, XivError> {
+        let mut row = Row::with_capacity(2 + self.column_count());
+        row.push(Value::UInt32(self.id));
+        if let Some(subid) = self.subid {
+            row.push(Value::UInt16(subid));
+        }
+        for value in self.values() {
+            row.push(value?.to_value()?);
+        }
+        Ok(row)
+    }
+}
+
+/// Keys followed by every column, like `Row` is serialized.
+impl Serialize for RowRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::SerializeSeq;
+
+        let keys = 1 + usize::from(self.subid.is_some());
+        let mut seq = serializer.serialize_seq(Some(keys + self.column_count()))?;
+        seq.serialize_element(&self.id)?;
+        if let Some(subid) = self.subid {
+            seq.serialize_element(&subid)?;
+        }
+        for value in self.values() {
+            seq.serialize_element(&value.map_err(ser::Error::custom)?)?;
+        }
+        seq.end()
+    }
+}
+
+/// Iterates rows (and subrows) of an .exd page without copying its data.
+/// Errors are reported at `location` of the page, e.g. as returned by
+/// `read_exd_pages`.
+pub fn page_rows<'a>(
+    exh: &'a Exh,
+    data: &'a [u8],
+    location: &'a ExdLocation,
+) -> Result<impl Iterator<Item = Result<RowRef<'a>, XivError>> + 'a, XivError> {
+    let header = ExdHeader::read(&mut Cursor::new(data))
+        .map_err(|e| XivError::ExdFileHeader(Box::new(location.clone()), e))?;
+
+    Ok(header.rows.into_iter().flat_map(move |row_ptr| {
+        let row_location = || {
+            Box::new(ExdLocation {
+                row_id: Some(row_ptr.id),
+                ..location.clone()
+            })
+        };
+        let mut cursor = Cursor::new(data);
+        cursor.set_position(row_ptr.offset as u64 + 4);
+        let subrow_count = match u16::read_be(&mut cursor) {
+            Ok(count) => count,
+            Err(e) => return vec![Err(XivError::ExdRowHeader(row_location(), e))],
+        };
+        let row_start = row_ptr.offset as usize + 6;
+
+        match exh.variant {
+            ExVariant::Normal => vec![Ok(RowRef {
+                exh,
+                data,
+                location,
+                id: row_ptr.id,
+                subid: None,
+                offset: row_start,
+            })],
+            ExVariant::SubRows => (0..subrow_count as usize)
+                .map(|i| {
+                    let start = row_start + i * (2 + exh.data_offset as usize);
+                    cursor.set_position(start as u64);
+                    let subid = u16::read_be(&mut cursor)
+                        .map_err(|e| XivError::ExdSubRowHeader(row_location(), e))?;
+                    Ok(RowRef {
+                        exh,
+                        data,
+                        location,
+                        id: row_ptr.id,
+                        subid: Some(subid),
+                        offset: start + 2,
+                    })
+                })
+                .collect(),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ex::{write::ExdWriter, ExColumn, ExPage},
+        sestring::SeString,
+    };
+
+    fn sample_exh(variant: ExVariant) -> Exh {
+        let column = |vtype, offset| ExColumn { vtype, offset };
+        Exh {
+            unk0: 3,
+            data_offset: 8,
+            column_count: 3,
+            page_count: 0,
+            language_count: 0,
+            unk1: 0,
+            u2: 0,
+            variant,
+            unk2: 0,
+            row_count: 0,
+            unk3: 0,
+            unk4: 0,
+            columns: vec![
+                column(ValueType::String, 0),
+                column(ValueType::Int16, 4),
+                column(ValueType::PackedBool2, 6),
+            ],
+            pages: vec![ExPage {
+                start_id: 0,
+                row_count: 0,
+            }],
+            languages: Vec::new(),
+        }
+    }
+
+    fn write_page(exh: &Exh, rows: &[Row]) -> Box<[u8]> {
+        let mut writer = ExdWriter::new(exh.clone());
+        for row in rows {
+            writer.push(row).unwrap();
+        }
+        writer.pages().remove(0).data
+    }
+
+    #[test]
+    fn borrowed_rows() {
+        let exh = sample_exh(ExVariant::Normal);
+        let markup = SeString::from_markup("a<br>b").unwrap();
+        let rows: Vec<Row> = vec![
+            vec![
+                Value::UInt32(1),
+                Value::String("foo".into()),
+                Value::Int16(-2),
+                Value::Bool(true),
+            ],
+            vec![
+                Value::UInt32(5),
+                Value::SeString(markup),
+                Value::Int16(7),
+                Value::Bool(false),
+            ],
+        ];
+        let data = write_page(&exh, &rows);
+        let location = ExdLocation {
+            sheet: Some("Sample".into()),
+            ..Default::default()
+        };
+
+        let refs: Vec<RowRef> = page_rows(&exh, &data, &location)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].id(), 1);
+        assert_eq!(refs[0].subid(), None);
+        assert_eq!(refs[0].get(0).unwrap(), ValueRef::Str(b"foo"));
+        assert_eq!(refs[0].get(0).unwrap().as_str(), Some("foo"));
+        assert_eq!(refs[0].get(2).unwrap(), ValueRef::Bool(true));
+        assert_eq!(refs[1].get(0).unwrap().as_str(), None);
+        assert_eq!(refs[1].get(0).unwrap().to_string(), "a<br>b");
+        let err = refs[1].get(3).unwrap_err().to_string();
+        assert!(err.contains("sheet Sample, row 5, column 3"), "{err}");
+
+        let read: Vec<Row> = refs.iter().map(|r| r.to_row().unwrap()).collect();
+        assert_eq!(read, rows);
+    }
+
+    #[test]
+    fn borrowed_subrows_serialize_as_rows() {
+        let exh = sample_exh(ExVariant::SubRows);
+        let rows: Vec<Row> = vec![
+            vec![
+                Value::UInt32(3),
+                Value::UInt16(0),
+                Value::String("foo".into()),
+                Value::Int16(1),
+                Value::Bool(false),
+            ],
+            vec![
+                Value::UInt32(3),
+                Value::UInt16(1),
+                Value::String("".into()),
+                Value::Int16(-1),
+                Value::Bool(true),
+            ],
+        ];
+        let data = write_page(&exh, &rows);
+        let location = ExdLocation::default();
+
+        let refs: Vec<RowRef> = page_rows(&exh, &data, &location)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(refs[1].subid(), Some(1));
+        // rows written back from their borrowed form produce the same page
+        let mut writer = ExdWriter::new(exh.clone());
+        for row in &refs {
+            writer.push(row).unwrap();
+        }
+        assert_eq!(writer.pages()[0].data, data);
+    }
+}
@@ -0,0 +1,39 @@
+use super::layout::ExhLayout;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::HashMap};
+
+/// Names of a sheet's columns, which .exh files do not carry themselves.
+///
+/// Meant to be stored next to the tooling, e.g. as one JSON file per sheet:
+/// `{"columns": ["Singular", "", "Plural"], "links": {"ItemUICategory": "ItemUICategory"},
+/// "layout": "str@0,u32@4,str@8"}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SheetSchema {
+    /// Name of every column by its index, empty for unknown ones.
+    #[serde(default)]
+    pub columns: Vec<Box<str>>,
+    /// Columns holding row ids of other sheets, by name of the target sheet.
+    #[serde(default)]
+    pub links: HashMap<Box<str>, Box<str>>,
+    /// Column layout the names were written for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<ExhLayout>,
+}
+
+impl SheetSchema {
+    /// Name of column by its index, falling back to `col_N`.
+    pub fn column_name(&self, idx: usize) -> Cow<'_, str> {
+        match self.columns.get(idx) {
+            Some(name) if !name.is_empty() => Cow::Borrowed(name),
+            _ => Cow::Owned(format!("col_{idx}")),
+        }
+    }
+
+    /// Index of column by its name or `col_N`.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|c| !c.is_empty() && c.as_ref() == name)
+            .or_else(|| name.strip_prefix("col_").and_then(|n| n.parse().ok()))
+    }
+}
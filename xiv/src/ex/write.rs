@@ -0,0 +1,657 @@
+use super::{ExField, ExPage, ExVariant, Exh, ValueType};
+use crate::error::XivError;
+use byteorder::{WriteBytesExt, BE, LE};
+use serde::{ser, Serialize};
+use std::{
+    collections::{btree_map, BTreeMap},
+    fmt,
+};
+
+/// Encodes sheet's header into .exh file.
+pub struct ExhWriter<'a> {
+    exh: &'a Exh,
+}
+
+impl<'a> ExhWriter<'a> {
+    pub fn new(exh: &'a Exh) -> Self {
+        Self { exh }
+    }
+
+    pub fn to_bytes(&self) -> Box<[u8]> {
+        let exh = self.exh;
+        let mut out = b"EXHF".to_vec();
+        // writing into Vec never fails
+        let w = &mut out;
+        w.write_u16::<BE>(exh.unk0).unwrap();
+        w.write_u16::<BE>(exh.data_offset).unwrap();
+        w.write_u16::<BE>(exh.columns.len() as u16).unwrap();
+        w.write_u16::<BE>(exh.pages.len() as u16).unwrap();
+        w.write_u16::<BE>(exh.languages.len() as u16).unwrap();
+        w.write_u16::<BE>(exh.unk1).unwrap();
+        w.write_u8(exh.u2).unwrap();
+        w.write_u8(exh.variant as u8).unwrap();
+        w.write_u16::<BE>(exh.unk2).unwrap();
+        w.write_u32::<BE>(exh.row_count).unwrap();
+        w.write_u32::<BE>(exh.unk3).unwrap();
+        w.write_u32::<BE>(exh.unk4).unwrap();
+        for column in &exh.columns {
+            w.write_u16::<BE>(column.vtype as u16).unwrap();
+            w.write_u16::<BE>(column.offset).unwrap();
+        }
+        for page in &exh.pages {
+            w.write_u32::<BE>(page.start_id).unwrap();
+            w.write_u32::<BE>(page.row_count).unwrap();
+        }
+        for locale in &exh.languages {
+            w.write_u16::<LE>(*locale as u16).unwrap();
+        }
+        out.into_boxed_slice()
+    }
+}
+
+/// Encoded .exd page file.
+pub struct ExdPage {
+    pub start_id: u32,
+    pub data: Box<[u8]>,
+}
+
+struct EncodedSubRow {
+    subid: u16,
+    fixed: Vec<u8>,
+    strings: Vec<u8>,
+    /// Positions of string offsets within `fixed`.
+    string_slots: Vec<usize>,
+}
+
+/// Builds .exd page files of a sheet from rows.
+///
+/// Rows are laid out as `ExdRowReader` expects them: row id first, then
+/// subrow id for `ExVariant::SubRows` sheets, then every column in order.
+pub struct ExdWriter {
+    exh: Exh,
+    /// Columns rows are written into, in order.
+    fields: Vec<ExField>,
+    rows: BTreeMap<u32, Vec<EncodedSubRow>>,
+}
+
+impl ExdWriter {
+    pub fn new(exh: Exh) -> Self {
+        Self {
+            fields: (0..exh.columns.len()).map(ExField::Column).collect(),
+            exh,
+            rows: BTreeMap::new(),
+        }
+    }
+
+    /// Takes a single `u8` value for packed bool columns sharing a byte,
+    /// as they are read with `ExdReadOptions::bitfields`.
+    pub fn with_bitfields(mut self) -> Self {
+        self.fields = self.exh.fields();
+        self
+    }
+
+    pub fn exh(&self) -> &Exh {
+        &self.exh
+    }
+
+    pub fn into_exh(self) -> Exh {
+        self.exh
+    }
+
+    /// Number of distinct row ids pushed so far.
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Encodes a row (`Row` or any struct mapped to the sheet's columns).
+    pub fn push<T: Serialize + ?Sized>(&mut self, row: &T) -> Result<(), XivError> {
+        let mut encoder = ExdRowWriter {
+            exh: &self.exh,
+            fields: &self.fields,
+            key_idx: 0,
+            id: 0,
+            subid: 0,
+            column_idx: 0,
+            fixed: vec![0; self.exh.data_offset as usize],
+            strings: Vec::new(),
+            string_slots: Vec::new(),
+        };
+        row.serialize(&mut encoder)
+            .map_err(|e| XivError::ExdSerialization(e.0))?;
+        if encoder.column_idx < self.fields.len() {
+            return Err(XivError::ExdSerialization(
+                format!(
+                    "row {} has {} columns, sheet has {}",
+                    encoder.id,
+                    encoder.column_idx,
+                    self.fields.len()
+                )
+                .into(),
+            ));
+        }
+
+        let subrows = self.rows.entry(encoder.id).or_default();
+        if self.exh.variant == ExVariant::Normal && !subrows.is_empty() {
+            let msg = format!("duplicate row {}", encoder.id);
+            return Err(XivError::ExdSerialization(msg.into()));
+        }
+        subrows.push(EncodedSubRow {
+            subid: encoder.subid,
+            fixed: encoder.fixed,
+            strings: encoder.strings,
+            string_slots: encoder.string_slots,
+        });
+        Ok(())
+    }
+
+    /// Replaces page layout of the header by one with up to `max_rows` rows
+    /// per page and updates its row count.
+    pub fn repaginate(&mut self, max_rows: usize) {
+        let ids: Vec<u32> = self.rows.keys().copied().collect();
+        self.exh.pages = ids
+            .chunks(max_rows.max(1))
+            .map(|chunk| ExPage {
+                start_id: chunk[0],
+                row_count: chunk.len() as u32,
+            })
+            .collect();
+        if self.exh.pages.is_empty() {
+            self.exh.pages.push(ExPage {
+                start_id: 0,
+                row_count: 0,
+            });
+        }
+        self.exh.row_count = ids.len() as u32;
+    }
+
+    /// Keeps page layout of the header, but recounts rows of its pages,
+    /// drops pages left without rows and updates its row count.
+    pub fn recount_pages(&mut self) {
+        let pages: Vec<ExPage> = self
+            .page_rows()
+            .into_iter()
+            .map(|(start_id, rows)| ExPage {
+                start_id,
+                row_count: rows.count() as u32,
+            })
+            .filter(|page| page.row_count > 0)
+            .collect();
+        match pages.is_empty() {
+            true => self.repaginate(1),
+            false => {
+                self.exh.pages = pages;
+                self.exh.row_count = self.rows.len() as u32;
+            }
+        }
+    }
+
+    /// Encodes rows into pages of the header, each row going into the last
+    /// page starting at or before its id.
+    pub fn pages(&self) -> Vec<ExdPage> {
+        self.page_rows()
+            .into_iter()
+            .map(|(start_id, rows)| ExdPage {
+                start_id,
+                data: self.encode_page(rows),
+            })
+            .collect()
+    }
+
+    /// Start ids of pages of the header along with rows going into them.
+    fn page_rows(&self) -> Vec<(u32, btree_map::Range<'_, u32, Vec<EncodedSubRow>>)> {
+        let mut starts: Vec<u32> = self.exh.pages.iter().map(|p| p.start_id).collect();
+        starts.sort_unstable();
+
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, start_id)| {
+                let from = if i == 0 { 0 } else { *start_id };
+                let rows = match starts.get(i + 1) {
+                    Some(end) => self.rows.range(from..*end),
+                    None => self.rows.range(from..),
+                };
+                (*start_id, rows)
+            })
+            .collect()
+    }
+
+    fn encode_page<'a>(
+        &self,
+        rows: impl Iterator<Item = (&'a u32, &'a Vec<EncodedSubRow>)>,
+    ) -> Box<[u8]> {
+        const HEADER_LEN: usize = 32;
+
+        let rows: Vec<_> = rows.collect();
+        let index_size = rows.len() * 8;
+        let mut index = Vec::with_capacity(index_size);
+        let mut data = Vec::new();
+
+        for (id, subrows) in rows {
+            let offset = HEADER_LEN + index_size + data.len();
+            index.write_u32::<BE>(*id).unwrap();
+            index.write_u32::<BE>(offset as u32).unwrap();
+
+            let body = self.encode_row(subrows);
+            data.write_u32::<BE>(body.len() as u32).unwrap();
+            data.write_u16::<BE>(subrows.len() as u16).unwrap();
+            data.extend(body);
+        }
+
+        let mut out = b"EXDF".to_vec();
+        out.write_u16::<BE>(2).unwrap();
+        out.write_u16::<BE>(0).unwrap();
+        out.write_u32::<BE>(index_size as u32).unwrap();
+        out.write_u32::<BE>(data.len() as u32).unwrap();
+        out.extend([0; 16]);
+        out.extend(index);
+        out.extend(data);
+        out.into_boxed_slice()
+    }
+
+    fn encode_row(&self, subrows: &[EncodedSubRow]) -> Vec<u8> {
+        let is_subrows = self.exh.variant == ExVariant::SubRows;
+        let subrow_len = self.exh.data_offset as usize + if is_subrows { 2 } else { 0 };
+
+        let mut body = Vec::new();
+        let mut strings_len = 0;
+        for (i, subrow) in subrows.iter().enumerate() {
+            // string data follows all subrows, while offsets are relative to
+            // the end of each subrow
+            let strings_base = (subrows.len() - 1 - i) * subrow_len + strings_len;
+            let mut fixed = subrow.fixed.clone();
+            for slot in &subrow.string_slots {
+                let rel = u32::from_be_bytes(fixed[*slot..*slot + 4].try_into().unwrap());
+                let abs = rel + strings_base as u32;
+                fixed[*slot..*slot + 4].copy_from_slice(&abs.to_be_bytes());
+            }
+            if is_subrows {
+                body.write_u16::<BE>(subrow.subid).unwrap();
+            }
+            body.extend(fixed);
+            strings_len += subrow.strings.len();
+        }
+        for subrow in subrows {
+            body.extend(&subrow.strings);
+        }
+        body.resize((body.len() + 3) & !3, 0);
+        body
+    }
+}
+
+#[derive(Debug)]
+struct ExdSerializerError(Box<str>);
+
+impl fmt::Display for ExdSerializerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ExdSerializerError {}
+
+impl ser::Error for ExdSerializerError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string().into_boxed_str())
+    }
+}
+
+/// Serializer which lays out a row the same way `ExdRowReader` reads it.
+struct ExdRowWriter<'a> {
+    exh: &'a Exh,
+    fields: &'a [ExField],
+    key_idx: usize,
+    id: u32,
+    subid: u16,
+    column_idx: usize,
+    fixed: Vec<u8>,
+    strings: Vec<u8>,
+    string_slots: Vec<usize>,
+}
+
+enum Scalar<'v> {
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Bytes(&'v [u8]),
+}
+
+impl ExdRowWriter<'_> {
+    fn key_len(&self) -> usize {
+        match self.exh.variant {
+            ExVariant::Normal => 1,
+            ExVariant::SubRows => 2,
+        }
+    }
+
+    fn write(&mut self, value: Scalar) -> Result<(), ExdSerializerError> {
+        if self.key_idx < self.key_len() {
+            let key = match value {
+                Scalar::Int(v) => v,
+                _ => return Err(ser::Error::custom("row and subrow ids must be integers")),
+            };
+            if self.key_idx == 0 {
+                self.id = u32::try_from(key).map_err(ser::Error::custom)?;
+            } else {
+                self.subid = u16::try_from(key).map_err(ser::Error::custom)?;
+            }
+            self.key_idx += 1;
+            return Ok(());
+        }
+
+        let field = self.fields.get(self.column_idx).ok_or_else(|| {
+            ExdSerializerError(
+                format!("row {} has more values than sheet has columns", self.id).into(),
+            )
+        })?;
+        let column_idx = field.column();
+        let column = self.exh.columns[column_idx];
+        let vtype = column.vtype;
+        let offset = column.offset as usize;
+        self.column_idx += 1;
+
+        let mismatch = move || {
+            let msg = format!("value of column {column_idx} does not fit {vtype}");
+            ExdSerializerError(msg.into())
+        };
+        let int = |v: &Scalar| match v {
+            Scalar::Bool(b) => Some(*b as i128),
+            Scalar::Int(i) => Some(*i),
+            _ => None,
+        };
+
+        macro_rules! put {
+            ($t:ty) => {{
+                let v = int(&value)
+                    .and_then(|v| <$t>::try_from(v).ok())
+                    .ok_or_else(mismatch)?;
+                self.put(offset, &v.to_be_bytes())
+            }};
+        }
+
+        if let Some(mask) = field.bitfield_mask() {
+            let v = int(&value)
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or_else(mismatch)?;
+            let byte = self.fixed.get_mut(offset).ok_or_else(mismatch)?;
+            *byte = *byte & !mask | v & mask;
+            return Ok(());
+        }
+        match vtype {
+            ValueType::Int8 => put!(i8),
+            ValueType::UInt8 => put!(u8),
+            ValueType::Int16 => put!(i16),
+            ValueType::UInt16 => put!(u16),
+            ValueType::Int32 => put!(i32),
+            ValueType::UInt32 => put!(u32),
+            ValueType::Int64 => put!(i64),
+            ValueType::UInt64 => put!(u64),
+            ValueType::Float32 => {
+                let v = match value {
+                    Scalar::Float(f) => f as f32,
+                    Scalar::Int(i) => i as f32,
+                    _ => return Err(mismatch()),
+                };
+                self.put(offset, &v.to_be_bytes())
+            }
+            ValueType::Bool => put!(u8),
+            ValueType::String => {
+                let bytes = match value {
+                    Scalar::Bytes(b) => b,
+                    _ => return Err(mismatch()),
+                };
+                let str_offset = self.strings.len() as u32;
+                self.strings.extend(bytes);
+                self.strings.push(0);
+                self.string_slots.push(offset);
+                self.put(offset, &str_offset.to_be_bytes())
+            }
+            packed => {
+                let mask = packed.packed_bit_mask().unwrap();
+                let set = int(&value).ok_or_else(mismatch)? != 0;
+                let byte = self.fixed.get_mut(offset).ok_or_else(mismatch)?;
+                if set {
+                    *byte |= mask;
+                } else {
+                    *byte &= !mask;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn put(&mut self, offset: usize, bytes: &[u8]) -> Result<(), ExdSerializerError> {
+        let slot = self
+            .fixed
+            .get_mut(offset..offset + bytes.len())
+            .ok_or_else(|| ExdSerializerError("column lies outside of row data".into()))?;
+        slot.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Writes zero value of the next column, used for `None` and unit values.
+    fn write_default(&mut self) -> Result<(), ExdSerializerError> {
+        let vtype = match self.key_idx < self.key_len() {
+            true => ValueType::UInt32,
+            false => self
+                .fields
+                .get(self.column_idx)
+                .map_or(ValueType::UInt32, |f| self.exh.columns[f.column()].vtype),
+        };
+        match vtype {
+            ValueType::String => self.write(Scalar::Bytes(b"")),
+            ValueType::Float32 => self.write(Scalar::Float(0.0)),
+            _ => self.write(Scalar::Int(0)),
+        }
+    }
+}
+
+type Res = Result<(), ExdSerializerError>;
+
+impl<'a, 'b> ser::Serializer for &'a mut ExdRowWriter<'b> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = ser::Impossible<(), ExdSerializerError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Res {
+        self.write(Scalar::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_i16(self, v: i16) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_i32(self, v: i32) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_i64(self, v: i64) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_u8(self, v: u8) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_u16(self, v: u16) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_u32(self, v: u32) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_u64(self, v: u64) -> Res {
+        self.write(Scalar::Int(v.into()))
+    }
+    fn serialize_f32(self, v: f32) -> Res {
+        self.write(Scalar::Float(v.into()))
+    }
+    fn serialize_f64(self, v: f64) -> Res {
+        self.write(Scalar::Float(v))
+    }
+    fn serialize_char(self, v: char) -> Res {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Res {
+        self.write(Scalar::Bytes(v.as_bytes()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Res {
+        self.write(Scalar::Bytes(v))
+    }
+    fn serialize_none(self) -> Res {
+        self.write_default()
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Res {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Res {
+        self.write_default()
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Res {
+        self.write_default()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+    ) -> Res {
+        self.serialize_u32(index)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Res {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Res {
+        value.serialize(self)
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, ExdSerializerError> {
+        Ok(self)
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self, ExdSerializerError> {
+        Ok(self)
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self, ExdSerializerError> {
+        Ok(self)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, ExdSerializerError> {
+        Ok(self)
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, ExdSerializerError> {
+        Err(ser::Error::custom("maps can not be mapped to exd columns"))
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self, ExdSerializerError> {
+        Ok(self)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, ExdSerializerError> {
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut ExdRowWriter<'_> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Res {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Res {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut ExdRowWriter<'_> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Res {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Res {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut ExdRowWriter<'_> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Res {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Res {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut ExdRowWriter<'_> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Res {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Res {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut ExdRowWriter<'_> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Res {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Res {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut ExdRowWriter<'_> {
+    type Ok = ();
+    type Error = ExdSerializerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Res {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Res {
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! `Option` of a key or link column, where `0` (or any other default value)
+//! stands for no value:
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! struct Item {
+//!     id: u32,
+//!     #[serde(with = "xiv::ex::zero_as_none")]
+//!     category: Option<u8>,
+//! }
+//! ```
+//!
+//! Unlike reading `Option<T>` alone, which is always `Some` since every
+//! column has a value, this reads all columns of `T` before deciding, so it
+//! works for tuples and structs spanning several columns too.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize + Default,
+    S: Serializer,
+{
+    match value {
+        Some(value) => value.serialize(serializer),
+        None => T::default().serialize(serializer),
+    }
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de> + Default + PartialEq,
+    D: Deserializer<'de>,
+{
+    let value = T::deserialize(deserializer)?;
+    Ok((value != T::default()).then_some(value))
+}
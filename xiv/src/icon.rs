@@ -0,0 +1,74 @@
+use crate::{dat::InnerFilePtr, error::XivError, ex::Locale, sqpack::SqPack};
+
+/// Path of an icon texture within SqPack repository, e.g.
+/// `ui/icon/021000/021001.tex`, or `ui/icon/021000/en/021001_hr1.tex` for
+/// localized high resolution one.
+pub fn icon_path(id: u32, locale: Locale, hires: bool) -> String {
+    let folder = id / 1000 * 1000;
+    let language = match locale {
+        Locale::None => String::new(),
+        locale => format!("{}/", locale.suffix().trim_start_matches('_')),
+    };
+    let resolution = if hires { "_hr1" } else { "" };
+    format!("ui/icon/{folder:06}/{language}{id:06}{resolution}.tex")
+}
+
+/// Paths an icon may be stored at, most preferred first: high resolution
+/// before normal one when `hires` is set, localized before shared one.
+pub fn icon_paths(id: u32, locale: Locale, hires: bool) -> Vec<String> {
+    let resolutions: &[bool] = if hires { &[true, false] } else { &[false] };
+    let locales: &[Locale] = match locale {
+        Locale::None => &[Locale::None],
+        _ => &[locale, Locale::None],
+    };
+    resolutions
+        .iter()
+        .flat_map(|hires| locales.iter().map(|locale| icon_path(id, *locale, *hires)))
+        .collect()
+}
+
+/// Finds the most preferred path of an icon that exists within SqPack
+/// repository, see `icon_paths`.
+pub fn find_icon(
+    repo: &SqPack,
+    id: u32,
+    locale: Locale,
+    hires: bool,
+) -> Result<Option<(String, InnerFilePtr)>, XivError> {
+    for path in icon_paths(id, locale, hires) {
+        if let Some(file) = repo.find(&path)? {
+            return Ok(Some((path, file)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            icon_path(21001, Locale::None, false),
+            "ui/icon/021000/021001.tex"
+        );
+        assert_eq!(
+            icon_path(7, Locale::None, true),
+            "ui/icon/000000/000007_hr1.tex"
+        );
+        assert_eq!(
+            icon_paths(121999, Locale::ChineseSimplified, true),
+            [
+                "ui/icon/121000/chs/121999_hr1.tex",
+                "ui/icon/121000/121999_hr1.tex",
+                "ui/icon/121000/chs/121999.tex",
+                "ui/icon/121000/121999.tex",
+            ]
+        );
+        assert_eq!(
+            icon_paths(65002, Locale::None, false),
+            ["ui/icon/065000/065002.tex"]
+        );
+    }
+}
@@ -1,8 +1,10 @@
 pub mod dat;
 pub mod error;
 pub mod ex;
+pub mod icon;
 pub mod index2;
 pub mod packid;
+pub mod sestring;
 pub mod sqpack;
 pub mod structs;
 pub mod tex;
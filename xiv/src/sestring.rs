@@ -0,0 +1,566 @@
+use crate::error::XivError;
+use serde::{de, Deserialize, Serialize};
+use std::fmt::{self, Write};
+
+mod encode;
+pub mod eval;
+
+const PAYLOAD_START: u8 = 0x02;
+const PAYLOAD_END: u8 = 0x03;
+
+macro_rules! macro_codes {
+    ($($name:ident = $code:literal => $tag:literal,)*) => {
+        /// Type of a macro payload embedded into SeString.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum MacroCode {
+            $($name,)*
+            Unknown(u8),
+        }
+
+        impl MacroCode {
+            pub fn from_u8(code: u8) -> Self {
+                match code {
+                    $($code => Self::$name,)*
+                    _ => Self::Unknown(code),
+                }
+            }
+
+            pub fn to_u8(self) -> u8 {
+                match self {
+                    $(Self::$name => $code,)*
+                    Self::Unknown(code) => code,
+                }
+            }
+
+            /// Name of the macro as used in markup, `None` for unknown macros.
+            pub fn tag(self) -> Option<&'static str> {
+                match self {
+                    $(Self::$name => Some($tag),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+
+            pub fn from_tag(tag: &str) -> Option<Self> {
+                match tag {
+                    $($tag => Some(Self::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+macro_codes! {
+    SetResetTime = 0x06 => "setresettime",
+    SetTime = 0x07 => "settime",
+    If = 0x08 => "if",
+    Switch = 0x09 => "switch",
+    PcName = 0x0A => "pcname",
+    IfPcGender = 0x0B => "ifpcgender",
+    IfPcName = 0x0C => "ifpcname",
+    Josa = 0x0D => "josa",
+    Josaro = 0x0E => "josaro",
+    IfSelf = 0x0F => "ifself",
+    NewLine = 0x10 => "br",
+    Wait = 0x11 => "wait",
+    Icon = 0x12 => "icon",
+    Color = 0x13 => "color",
+    EdgeColor = 0x14 => "edgecolor",
+    ShadowColor = 0x15 => "shadowcolor",
+    SoftHyphen = 0x16 => "shy",
+    Key = 0x17 => "key",
+    Scale = 0x18 => "scale",
+    Bold = 0x19 => "bold",
+    Italic = 0x1A => "italic",
+    Edge = 0x1B => "edge",
+    Shadow = 0x1C => "shadow",
+    NonBreakingSpace = 0x1D => "nbsp",
+    Icon2 = 0x1E => "icon2",
+    Hyphen = 0x1F => "hyphen",
+    Num = 0x20 => "num",
+    Hex = 0x21 => "hex",
+    Kilo = 0x22 => "kilo",
+    Byte = 0x23 => "byte",
+    Sec = 0x24 => "sec",
+    Time = 0x25 => "time",
+    Float = 0x26 => "float",
+    Link = 0x27 => "link",
+    Sheet = 0x28 => "sheet",
+    String = 0x29 => "string",
+    Caps = 0x2A => "caps",
+    Head = 0x2B => "head",
+    Split = 0x2C => "split",
+    HeadAll = 0x2D => "headall",
+    Fixed = 0x2E => "fixed",
+    Lower = 0x2F => "lower",
+    JaNoun = 0x30 => "janoun",
+    EnNoun = 0x31 => "ennoun",
+    DeNoun = 0x32 => "denoun",
+    FrNoun = 0x33 => "frnoun",
+    ChNoun = 0x34 => "chnoun",
+    LowerHead = 0x40 => "lowerhead",
+    ColorType = 0x48 => "colortype",
+    EdgeColorType = 0x49 => "edgecolortype",
+    Digit = 0x50 => "digit",
+    Ordinal = 0x51 => "ordinal",
+    Sound = 0x60 => "sound",
+    LevelPos = 0x61 => "levelpos",
+}
+
+/// Kind of a runtime parameter referenced by an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParamKind {
+    /// Local integer parameter (`lnum`).
+    Integer = 0xE8,
+    /// Global player parameter (`gnum`).
+    Player = 0xE9,
+    /// Local string parameter (`lstr`).
+    String = 0xEA,
+    /// Global object parameter (`gstr`).
+    Object = 0xEB,
+}
+
+impl ParamKind {
+    fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0xE8 => Some(Self::Integer),
+            0xE9 => Some(Self::Player),
+            0xEA => Some(Self::String),
+            0xEB => Some(Self::Object),
+            _ => None,
+        }
+    }
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::Integer => "lnum",
+            Self::Player => "gnum",
+            Self::String => "lstr",
+            Self::Object => "gstr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmpOp {
+    GreaterOrEqual = 0xE0,
+    Greater = 0xE1,
+    LessOrEqual = 0xE2,
+    Less = 0xE3,
+    Equal = 0xE4,
+    NotEqual = 0xE5,
+}
+
+impl CmpOp {
+    fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0xE0 => Some(Self::GreaterOrEqual),
+            0xE1 => Some(Self::Greater),
+            0xE2 => Some(Self::LessOrEqual),
+            0xE3 => Some(Self::Less),
+            0xE4 => Some(Self::Equal),
+            0xE5 => Some(Self::NotEqual),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::GreaterOrEqual => ">=",
+            Self::Greater => ">",
+            Self::LessOrEqual => "<=",
+            Self::Less => "<",
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+        }
+    }
+}
+
+/// Names of nullary expressions (`0xD0..=0xDF` and `0xEC`), mostly clock values.
+fn placeholder_tag(code: u8) -> Option<&'static str> {
+    match code {
+        0xD8 => Some("t_msec"),
+        0xD9 => Some("t_sec"),
+        0xDA => Some("t_min"),
+        0xDB => Some("t_hour"),
+        0xDC => Some("t_day"),
+        0xDD => Some("t_wday"),
+        0xDE => Some("t_mon"),
+        0xDF => Some("t_year"),
+        0xEC => Some("stackcolor"),
+        _ => None,
+    }
+}
+
+/// Argument of a macro payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Int(u32),
+    String(SeString),
+    /// Nullary expression, such as current time or color stack top.
+    Placeholder(u8),
+    Param(ParamKind, Box<Expr>),
+    Cmp(CmpOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    pub code: MacroCode,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Text(Box<str>),
+    Macro(Macro),
+}
+
+/// Game's rich text string: UTF-8 text interleaved with binary macro payloads
+/// (`0x02 <code> <len> <args...> 0x03`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeString {
+    pub nodes: Vec<Node>,
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    base: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> XivError {
+        XivError::SeString(self.base + self.pos)
+    }
+
+    fn byte(&mut self) -> Result<u8, XivError> {
+        let b = *self.data.get(self.pos).ok_or_else(|| self.error())?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], XivError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| self.error())?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn packed_int(&mut self, marker: u8) -> Result<u32, XivError> {
+        match marker {
+            0x01..=0xCF => Ok(marker as u32 - 1),
+            0xF0..=0xFE => {
+                let flags = (marker + 1) & 0xF;
+                let mut value = 0u32;
+                for shift in [24, 16, 8, 0] {
+                    if flags & (1 << (shift / 8)) != 0 {
+                        value |= (self.byte()? as u32) << shift;
+                    }
+                }
+                Ok(value)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, XivError> {
+        let marker = self.byte()?;
+        if let Some(kind) = ParamKind::from_u8(marker) {
+            return Ok(Expr::Param(kind, Box::new(self.expr()?)));
+        }
+        if let Some(op) = CmpOp::from_u8(marker) {
+            let lhs = self.expr()?;
+            let rhs = self.expr()?;
+            return Ok(Expr::Cmp(op, Box::new(lhs), Box::new(rhs)));
+        }
+        match marker {
+            0xD0..=0xDF | 0xEC => Ok(Expr::Placeholder(marker)),
+            0xFF => {
+                let len_marker = self.byte()?;
+                let len = self.packed_int(len_marker)? as usize;
+                let base = self.base + self.pos;
+                let bytes = self.take(len)?;
+                SeString::parse_at(bytes, base).map(Expr::String)
+            }
+            _ => self.packed_int(marker).map(Expr::Int),
+        }
+    }
+
+    fn payload(&mut self) -> Result<Macro, XivError> {
+        let code = MacroCode::from_u8(self.byte()?);
+        let len_marker = self.byte()?;
+        let len = self.packed_int(len_marker)? as usize;
+        let base = self.base + self.pos;
+        let body = self.take(len)?;
+        if self.byte()? != PAYLOAD_END {
+            return Err(self.error());
+        }
+
+        let mut body = Parser {
+            data: body,
+            pos: 0,
+            base,
+        };
+        let mut args = Vec::new();
+        while body.pos < body.data.len() {
+            args.push(body.expr()?);
+        }
+        Ok(Macro { code, args })
+    }
+}
+
+impl SeString {
+    /// Parses SeString from its binary form (without the trailing NUL).
+    pub fn parse(data: &[u8]) -> Result<Self, XivError> {
+        Self::parse_at(data, 0)
+    }
+
+    fn parse_at(data: &[u8], base: usize) -> Result<Self, XivError> {
+        let mut parser = Parser { data, pos: 0, base };
+        let mut nodes = Vec::new();
+        let mut text_start = 0;
+
+        while parser.pos < data.len() {
+            if data[parser.pos] != PAYLOAD_START {
+                parser.pos += 1;
+                continue;
+            }
+            if text_start < parser.pos {
+                let text = String::from_utf8_lossy(&data[text_start..parser.pos]);
+                nodes.push(Node::Text(text.into()));
+            }
+            parser.pos += 1;
+            nodes.push(Node::Macro(parser.payload()?));
+            text_start = parser.pos;
+        }
+        if text_start < data.len() {
+            let text = String::from_utf8_lossy(&data[text_start..]);
+            nodes.push(Node::Text(text.into()));
+        }
+
+        Ok(Self { nodes })
+    }
+
+    /// Returns `true` if the string has no macro payloads.
+    pub fn is_plain(&self) -> bool {
+        self.nodes.iter().all(|n| matches!(n, Node::Text(_)))
+    }
+
+    /// Renders text only, keeping line breaks and spacing macros and dropping the rest.
+    pub fn to_plain_text(&self) -> String {
+        let mut out = String::new();
+        for node in &self.nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Macro(m) => match m.code {
+                    MacroCode::NewLine => out.push('\n'),
+                    MacroCode::NonBreakingSpace => out.push('\u{a0}'),
+                    MacroCode::Hyphen => out.push('-'),
+                    _ => {}
+                },
+            }
+        }
+        out
+    }
+
+    /// Renders human-readable markup, e.g. `<if([gnum4=1],his,her)>`.
+    pub fn to_markup(&self) -> String {
+        self.to_string()
+    }
+
+    fn write_markup(&self, f: &mut impl Write, escaped: &[char]) -> fmt::Result {
+        for node in &self.nodes {
+            match node {
+                Node::Text(text) => {
+                    for c in text.chars() {
+                        if c == '\\' || escaped.contains(&c) {
+                            f.write_char('\\')?;
+                        }
+                        f.write_char(c)?;
+                    }
+                }
+                Node::Macro(m) => m.write_markup(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Characters which have to be escaped within text of markup macro arguments.
+const ARG_ESCAPED: &[char] = &['<', ',', ')', '"'];
+
+/// Prefixes of markup expressions which a string argument must not start with
+/// unless quoted.
+const EXPR_PREFIXES: &[&str] = &[
+    "lnum",
+    "gnum",
+    "lstr",
+    "gstr",
+    "t_",
+    "stackcolor",
+    "placeholder",
+];
+
+impl Macro {
+    fn write_markup(&self, f: &mut impl Write) -> fmt::Result {
+        f.write_char('<')?;
+        match self.code.tag() {
+            Some(tag) => f.write_str(tag)?,
+            None => write!(f, "x{:02x}", self.code.to_u8())?,
+        }
+        if !self.args.is_empty() {
+            f.write_char('(')?;
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    f.write_char(',')?;
+                }
+                arg.write_markup(f)?;
+            }
+            f.write_char(')')?;
+        }
+        f.write_char('>')
+    }
+}
+
+impl Expr {
+    fn write_markup(&self, f: &mut impl Write) -> fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{value}"),
+            Self::String(s) => {
+                let mut text = String::new();
+                s.write_markup(&mut text, ARG_ESCAPED)?;
+                let quoted = text.is_empty()
+                    || text.starts_with(|c: char| c.is_ascii_digit() || c == '[')
+                    || EXPR_PREFIXES.iter().any(|p| text.starts_with(p));
+                if quoted {
+                    write!(f, "\"{text}\"")
+                } else {
+                    f.write_str(&text)
+                }
+            }
+            Self::Placeholder(code) => match placeholder_tag(*code) {
+                Some(tag) => f.write_str(tag),
+                None => write!(f, "placeholder({code})"),
+            },
+            Self::Param(kind, arg) => match arg.as_ref() {
+                Self::Int(value) => write!(f, "{}{value}", kind.tag()),
+                arg => {
+                    write!(f, "{}(", kind.tag())?;
+                    arg.write_markup(f)?;
+                    f.write_char(')')
+                }
+            },
+            Self::Cmp(op, lhs, rhs) => {
+                f.write_char('[')?;
+                lhs.write_markup(f)?;
+                f.write_str(op.symbol())?;
+                rhs.write_markup(f)?;
+                f.write_char(']')
+            }
+        }
+    }
+}
+
+impl fmt::Display for SeString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_markup(f, &['<'])
+    }
+}
+
+impl From<&str> for SeString {
+    fn from(text: &str) -> Self {
+        let nodes = if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![Node::Text(text.into())]
+        };
+        Self { nodes }
+    }
+}
+
+impl Serialize for SeString {
+    /// Serializes into markup for human-readable formats and into binary form otherwise.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+struct SeStringVisitor;
+
+impl<'de> de::Visitor<'de> for SeStringVisitor {
+    type Value = SeString;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SeString")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        SeString::parse(v).map_err(E::custom)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        SeString::from_markup(v).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for SeString {
+    /// Deserializes from markup for human-readable formats and from binary form otherwise.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(SeStringVisitor)
+        } else {
+            deserializer.deserialize_bytes(SeStringVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain() {
+        let s = SeString::parse(b"Miqo'te").unwrap();
+        assert!(s.is_plain());
+        assert_eq!(s.to_plain_text(), "Miqo'te");
+        assert_eq!(s.to_markup(), "Miqo'te");
+    }
+
+    #[test]
+    fn parse_macros() {
+        // "a<br>b" followed by a color payload with a 2-byte packed integer
+        let data = b"a\x02\x10\x01\x03b\x02\x13\x04\xF5\xFF\x80\x03";
+        let s = SeString::parse(data).unwrap();
+        assert_eq!(s.to_plain_text(), "a\nb");
+        assert_eq!(s.to_markup(), "a<br>b<color(16744448)>");
+    }
+
+    #[test]
+    fn parse_nested() {
+        // <if([gnum4=1],his,her)>
+        let data = b"\x02\x08\x0F\xE4\xE9\x05\x02\xFF\x04his\xFF\x04her\x03";
+        let s = SeString::parse(data).unwrap();
+        assert_eq!(s.to_markup(), "<if([gnum4=1],his,her)>");
+        assert_eq!(s.to_plain_text(), "");
+    }
+
+    #[test]
+    fn deserialize_markup() {
+        use serde::de::{value::StrDeserializer, IntoDeserializer};
+        let markup: StrDeserializer<de::value::Error> = "a<br>b\\<".into_deserializer();
+        let s = SeString::deserialize(markup).unwrap();
+        assert_eq!(s.to_plain_text(), "a\nb<");
+        assert_eq!(s.to_markup(), "a<br>b\\<");
+    }
+
+    #[test]
+    fn parse_truncated() {
+        assert!(SeString::parse(b"a\x02\x10\x05\x03").is_err());
+    }
+}
@@ -0,0 +1,349 @@
+use super::{
+    placeholder_tag, CmpOp, Expr, Macro, MacroCode, Node, ParamKind, SeString, EXPR_PREFIXES,
+    PAYLOAD_END, PAYLOAD_START,
+};
+use crate::error::XivError;
+use std::{iter::Peekable, str::CharIndices, str::FromStr};
+
+fn write_packed_int(out: &mut Vec<u8>, value: u32) {
+    if value < 0xCF {
+        out.push(value as u8 + 1);
+        return;
+    }
+
+    let bytes = value.to_be_bytes();
+    let flags = bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b != 0)
+        .fold(0u8, |flags, (i, _)| flags | (8 >> i));
+    out.push(0xF0 + flags - 1);
+    out.extend(bytes.iter().filter(|b| **b != 0));
+}
+
+impl Expr {
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Int(value) => write_packed_int(out, *value),
+            Self::String(s) => {
+                let bytes = s.to_bytes();
+                out.push(0xFF);
+                write_packed_int(out, bytes.len() as u32);
+                out.extend(bytes);
+            }
+            Self::Placeholder(code) => out.push(*code),
+            Self::Param(kind, arg) => {
+                out.push(*kind as u8);
+                arg.write_bytes(out);
+            }
+            Self::Cmp(op, lhs, rhs) => {
+                out.push(*op as u8);
+                lhs.write_bytes(out);
+                rhs.write_bytes(out);
+            }
+        }
+    }
+}
+
+impl Macro {
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        for arg in &self.args {
+            arg.write_bytes(&mut body);
+        }
+        out.push(PAYLOAD_START);
+        out.push(self.code.to_u8());
+        write_packed_int(out, body.len() as u32);
+        out.extend(body);
+        out.push(PAYLOAD_END);
+    }
+}
+
+impl SeString {
+    /// Encodes SeString into its binary form (without the trailing NUL).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for node in &self.nodes {
+            match node {
+                Node::Text(text) => out.extend(text.as_bytes()),
+                Node::Macro(m) => m.write_bytes(&mut out),
+            }
+        }
+        out
+    }
+
+    /// Parses markup produced by `to_markup`.
+    pub fn from_markup(markup: &str) -> Result<Self, XivError> {
+        let mut parser = MarkupParser {
+            chars: markup.char_indices().peekable(),
+            len: markup.len(),
+        };
+        let s = parser.string(&[])?;
+        match parser.chars.peek() {
+            None => Ok(s),
+            Some(_) => Err(parser.error()),
+        }
+    }
+}
+
+impl FromStr for SeString {
+    type Err = XivError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_markup(s)
+    }
+}
+
+#[derive(Clone)]
+struct MarkupParser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+}
+
+impl MarkupParser<'_> {
+    fn error(&mut self) -> XivError {
+        let pos = self.chars.peek().map_or(self.len, |(i, _)| *i);
+        XivError::SeStringMarkup(pos)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), XivError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            word.push(c);
+            self.chars.next();
+        }
+        word
+    }
+
+    /// Parses text with macros until one of `terminators` or end of input.
+    fn string(&mut self, terminators: &[char]) -> Result<SeString, XivError> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if terminators.contains(&c) {
+                break;
+            }
+            self.chars.next();
+            match c {
+                '\\' => text.push(self.chars.next().ok_or_else(|| self.error())?.1),
+                '<' => {
+                    if !text.is_empty() {
+                        nodes.push(Node::Text(std::mem::take(&mut text).into()));
+                    }
+                    nodes.push(Node::Macro(self.macro_body()?));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(text.into()));
+        }
+        Ok(SeString { nodes })
+    }
+
+    fn macro_body(&mut self) -> Result<Macro, XivError> {
+        let tag = self.word();
+        let code = match MacroCode::from_tag(&tag) {
+            Some(code) => code,
+            None => tag
+                .strip_prefix('x')
+                .filter(|hex| hex.len() == 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .map(MacroCode::from_u8)
+                .ok_or_else(|| self.error())?,
+        };
+
+        let mut args = Vec::new();
+        if self.peek() == Some('(') {
+            self.chars.next();
+            loop {
+                args.push(self.arg()?);
+                match self.peek() {
+                    Some(',') => {
+                        self.chars.next();
+                    }
+                    Some(')') => {
+                        self.chars.next();
+                        break;
+                    }
+                    _ => return Err(self.error()),
+                }
+            }
+        }
+        self.expect('>')?;
+        Ok(Macro { code, args })
+    }
+
+    fn arg(&mut self) -> Result<Expr, XivError> {
+        if self.peek() == Some('"') {
+            self.chars.next();
+            let s = self.string(&['"'])?;
+            self.expect('"')?;
+            return Ok(Expr::String(s));
+        }
+
+        let checkpoint = self.clone();
+        match self.expr() {
+            Ok(expr) if matches!(self.peek(), Some(',' | ')')) => Ok(expr),
+            _ => {
+                *self = checkpoint;
+                self.string(&[',', ')']).map(Expr::String)
+            }
+        }
+    }
+
+    fn int(&mut self) -> Result<u32, XivError> {
+        let mut digits = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            digits.push(c);
+            self.chars.next();
+        }
+        digits.parse().map_err(|_| self.error())
+    }
+
+    fn expr(&mut self) -> Result<Expr, XivError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.int().map(Expr::Int),
+            Some('[') => {
+                self.chars.next();
+                let lhs = self.expr()?;
+                let op = self.cmp_op()?;
+                let rhs = self.expr()?;
+                self.expect(']')?;
+                Ok(Expr::Cmp(op, Box::new(lhs), Box::new(rhs)))
+            }
+            Some(_) => {
+                let word = self.word();
+                if !EXPR_PREFIXES.iter().any(|p| word.starts_with(p)) {
+                    return Err(self.error());
+                }
+                if let Some(code) =
+                    (0xD0..=0xEC).find(|c| placeholder_tag(*c) == Some(word.as_str()))
+                {
+                    return Ok(Expr::Placeholder(code));
+                }
+                if word == "placeholder" {
+                    self.expect('(')?;
+                    let code = u8::try_from(self.int()?).map_err(|_| self.error())?;
+                    self.expect(')')?;
+                    return Ok(Expr::Placeholder(code));
+                }
+
+                let kind = [
+                    ParamKind::Integer,
+                    ParamKind::Player,
+                    ParamKind::String,
+                    ParamKind::Object,
+                ]
+                .into_iter()
+                .find(|k| word.starts_with(k.tag()))
+                .ok_or_else(|| self.error())?;
+                let index = &word[kind.tag().len()..];
+                let arg = if index.is_empty() {
+                    self.expect('(')?;
+                    let arg = self.expr()?;
+                    self.expect(')')?;
+                    arg
+                } else {
+                    Expr::Int(index.parse().map_err(|_| self.error())?)
+                };
+                Ok(Expr::Param(kind, Box::new(arg)))
+            }
+            None => Err(self.error()),
+        }
+    }
+
+    fn cmp_op(&mut self) -> Result<CmpOp, XivError> {
+        let first = self.chars.next().map(|(_, c)| c);
+        let second_eq = self.peek() == Some('=');
+        let op = match (first, second_eq) {
+            (Some('>'), true) => CmpOp::GreaterOrEqual,
+            (Some('<'), true) => CmpOp::LessOrEqual,
+            (Some('!'), true) => CmpOp::NotEqual,
+            (Some('>'), false) => return Ok(CmpOp::Greater),
+            (Some('<'), false) => return Ok(CmpOp::Less),
+            (Some('='), false) => return Ok(CmpOp::Equal),
+            _ => return Err(self.error()),
+        };
+        self.chars.next();
+        Ok(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packed(value: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_packed_int(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn pack_integers() {
+        assert_eq!(packed(0), [0x01]);
+        assert_eq!(packed(0xCE), [0xCF]);
+        assert_eq!(packed(0xCF), [0xF0, 0xCF]);
+        assert_eq!(packed(0x100), [0xF1, 0x01]);
+        assert_eq!(packed(0xFF8000), [0xF5, 0xFF, 0x80]);
+        assert_eq!(packed(0x12D687), [0xF6, 0x12, 0xD6, 0x87]);
+        assert_eq!(packed(u32::MAX), [0xFE, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let samples: &[&[u8]] = &[
+            b"Miqo'te",
+            b"a\x02\x10\x01\x03b\x02\x13\x04\xF5\xFF\x80\x03",
+            b"\x02\x08\x0F\xE4\xE9\x05\x02\xFF\x04his\xFF\x04her\x03",
+            b"\x02\x0A\x02\x02\x03: \x02\x22\x08\xF6\x12\xD6\x87\xFF\x02,\x03",
+        ];
+        for sample in samples {
+            let s = SeString::parse(sample).unwrap();
+            assert_eq!(s.to_bytes(), *sample);
+        }
+    }
+
+    #[test]
+    fn markup_round_trip() {
+        let samples = [
+            "plain, text (with) \"quotes\"",
+            "a<br>b<color(16744448)>c<color(stackcolor)>",
+            "<if([gnum4=1],his,her)>",
+            "<if([lnum(lnum1)>=2],\"12 apples\",\"\",\"lnum\")>",
+            "<switch(lnum1,one,<color(1)>t\\,w\\)o<color(stackcolor)>,three)>",
+            "<sheet(Item,lnum2,0)> \\<not a macro> <x4a(1,2)> <num(placeholder(208))>",
+        ];
+        for sample in samples {
+            let s = SeString::from_markup(sample).unwrap();
+            assert_eq!(s.to_markup(), sample);
+            assert_eq!(SeString::parse(&s.to_bytes()).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn markup_errors() {
+        assert!(SeString::from_markup("<if(1,2").is_err());
+        assert!(SeString::from_markup("<nosuchmacro>").is_err());
+        assert!(SeString::from_markup("<if([1~2])>").is_ok()); // falls back to string argument
+        assert!(SeString::from_markup("trailing \\").is_err());
+    }
+}
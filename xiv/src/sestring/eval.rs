@@ -0,0 +1,531 @@
+use super::{CmpOp, Expr, Macro, MacroCode, Node, ParamKind, SeString};
+use crate::{
+    error::XivError,
+    ex::{read_exd, read_exh, ExVariant, Locale, Row, Value},
+    sqpack::SqPack,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gender {
+    #[default]
+    Male = 0,
+    Female = 1,
+}
+
+/// Runtime values which macros of SeString refer to.
+///
+/// Every method has a neutral default, so implementations only need to
+/// provide what their strings actually use.
+pub trait EvalContext {
+    /// Name of an object (`pcname`, `ifpcname`). Ids are client object ids.
+    fn object_name(&self, _object_id: u32) -> String {
+        String::new()
+    }
+
+    /// Gender of an object (`ifpcgender`).
+    fn object_gender(&self, _object_id: u32) -> Gender {
+        Gender::Male
+    }
+
+    /// Whether an object is the local player (`ifself`).
+    fn is_self(&self, _object_id: u32) -> bool {
+        true
+    }
+
+    /// Global player parameter (`gnum`), e.g. 4 is player's gender.
+    fn player_param(&self, _index: u32) -> u32 {
+        0
+    }
+
+    /// Local integer parameter (`lnum`), 1-based.
+    fn integer_param(&self, _index: u32) -> u32 {
+        0
+    }
+
+    /// Local string parameter (`lstr`), 1-based.
+    fn string_param(&self, _index: u32) -> SeString {
+        SeString::default()
+    }
+
+    /// Global object parameter (`gstr`).
+    fn object_param(&self, _index: u32) -> SeString {
+        SeString::default()
+    }
+
+    /// Value of a nullary expression, such as clock fields (`t_hour` etc.).
+    fn placeholder(&self, _code: u8) -> u32 {
+        0
+    }
+
+    /// Column value of a sheet row for `sheet` and noun macros.
+    fn sheet_value(
+        &self,
+        _sheet: &str,
+        _row: u32,
+        _column: u32,
+    ) -> Result<Option<Value>, XivError> {
+        Ok(None)
+    }
+}
+
+type SheetRows = Rc<HashMap<u32, Row>>;
+
+/// Context which resolves sheet lookups through `read_exd` and keeps player
+/// related values in plain fields.
+pub struct SqPackContext {
+    repo: Arc<SqPack>,
+    locale: Locale,
+    sheets: RefCell<HashMap<Box<str>, SheetRows>>,
+    pub player_name: String,
+    pub player_gender: Gender,
+    pub player_params: HashMap<u32, u32>,
+    pub integer_params: Vec<u32>,
+    pub string_params: Vec<SeString>,
+}
+
+impl SqPackContext {
+    pub fn new(repo: Arc<SqPack>, locale: Locale) -> Self {
+        Self {
+            repo,
+            locale,
+            sheets: Default::default(),
+            player_name: String::new(),
+            player_gender: Gender::Male,
+            player_params: HashMap::new(),
+            integer_params: Vec::new(),
+            string_params: Vec::new(),
+        }
+    }
+
+    fn sheet(&self, name: &str) -> Result<SheetRows, XivError> {
+        if let Some(rows) = self.sheets.borrow().get(name) {
+            return Ok(rows.clone());
+        }
+
+        let key_len = match read_exh(self.repo.clone(), name)?.variant {
+            ExVariant::Normal => 1,
+            ExVariant::SubRows => 2,
+        };
+        let mut rows = HashMap::new();
+        for row in read_exd::<Row>(self.repo.clone(), name, self.locale)? {
+            let mut row = row?;
+            if let Some(Value::UInt32(id)) = row.first() {
+                let id = *id;
+                row.drain(..key_len);
+                rows.entry(id).or_insert(row);
+            }
+        }
+
+        let rows = Rc::new(rows);
+        self.sheets.borrow_mut().insert(name.into(), rows.clone());
+        Ok(rows)
+    }
+}
+
+impl EvalContext for SqPackContext {
+    fn object_name(&self, _object_id: u32) -> String {
+        self.player_name.clone()
+    }
+
+    fn object_gender(&self, _object_id: u32) -> Gender {
+        self.player_gender
+    }
+
+    fn player_param(&self, index: u32) -> u32 {
+        match self.player_params.get(&index) {
+            Some(value) => *value,
+            None if index == 4 => self.player_gender as u32,
+            None => 0,
+        }
+    }
+
+    fn integer_param(&self, index: u32) -> u32 {
+        let index = index.checked_sub(1).map(|i| i as usize);
+        index
+            .and_then(|i| self.integer_params.get(i))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn string_param(&self, index: u32) -> SeString {
+        let index = index.checked_sub(1).map(|i| i as usize);
+        index
+            .and_then(|i| self.string_params.get(i))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn sheet_value(&self, sheet: &str, row: u32, column: u32) -> Result<Option<Value>, XivError> {
+        let rows = self.sheet(sheet)?;
+        let value = rows.get(&row).and_then(|r| r.get(column as usize));
+        Ok(value.cloned())
+    }
+}
+
+/// Context of a `sheet` macro, which passes its extra arguments to the looked
+/// up string as local integer parameters.
+struct ScopedContext<'a> {
+    parent: &'a dyn EvalContext,
+    integer_params: Vec<u32>,
+}
+
+impl EvalContext for ScopedContext<'_> {
+    fn object_name(&self, object_id: u32) -> String {
+        self.parent.object_name(object_id)
+    }
+    fn object_gender(&self, object_id: u32) -> Gender {
+        self.parent.object_gender(object_id)
+    }
+    fn is_self(&self, object_id: u32) -> bool {
+        self.parent.is_self(object_id)
+    }
+    fn player_param(&self, index: u32) -> u32 {
+        self.parent.player_param(index)
+    }
+    fn integer_param(&self, index: u32) -> u32 {
+        let index = index.checked_sub(1).map(|i| i as usize);
+        index
+            .and_then(|i| self.integer_params.get(i))
+            .copied()
+            .unwrap_or(0)
+    }
+    fn string_param(&self, index: u32) -> SeString {
+        self.parent.string_param(index)
+    }
+    fn object_param(&self, index: u32) -> SeString {
+        self.parent.object_param(index)
+    }
+    fn placeholder(&self, code: u8) -> u32 {
+        self.parent.placeholder(code)
+    }
+    fn sheet_value(&self, sheet: &str, row: u32, column: u32) -> Result<Option<Value>, XivError> {
+        self.parent.sheet_value(sheet, row, column)
+    }
+}
+
+/// How deep `sheet` macros may nest strings of other rows, to stop rows that
+/// refer to themselves.
+const MAX_SHEET_DEPTH: u32 = 32;
+
+struct Evaluator<'a> {
+    ctx: &'a dyn EvalContext,
+    /// Count of `sheet` macros the evaluated string was looked up through
+    depth: u32,
+}
+
+impl Evaluator<'_> {
+    fn int(&self, expr: &Expr) -> Result<u32, XivError> {
+        Ok(match expr {
+            Expr::Int(value) => *value,
+            Expr::Placeholder(code) => self.ctx.placeholder(*code),
+            Expr::Param(ParamKind::Integer, index) => self.ctx.integer_param(self.int(index)?),
+            Expr::Param(ParamKind::Player, index) => self.ctx.player_param(self.int(index)?),
+            Expr::Cmp(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.int(lhs)?, self.int(rhs)?);
+                let result = match op {
+                    CmpOp::GreaterOrEqual => lhs >= rhs,
+                    CmpOp::Greater => lhs > rhs,
+                    CmpOp::LessOrEqual => lhs <= rhs,
+                    CmpOp::Less => lhs < rhs,
+                    CmpOp::Equal => lhs == rhs,
+                    CmpOp::NotEqual => lhs != rhs,
+                };
+                result as u32
+            }
+            Expr::String(_) | Expr::Param(ParamKind::String | ParamKind::Object, _) => {
+                self.text(expr)?.trim().parse().unwrap_or(0)
+            }
+        })
+    }
+
+    fn text(&self, expr: &Expr) -> Result<String, XivError> {
+        match expr {
+            Expr::String(s) => self.string(s),
+            Expr::Param(ParamKind::String, index) => {
+                self.string(&self.ctx.string_param(self.int(index)?))
+            }
+            Expr::Param(ParamKind::Object, index) => {
+                self.string(&self.ctx.object_param(self.int(index)?))
+            }
+            expr => self.int(expr).map(|v| v.to_string()),
+        }
+    }
+
+    fn string(&self, s: &SeString) -> Result<String, XivError> {
+        let mut out = String::new();
+        for node in &s.nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Macro(m) => self.eval_macro(m, &mut out)?,
+            }
+        }
+        Ok(out)
+    }
+
+    fn arg_int(&self, m: &Macro, idx: usize) -> Result<u32, XivError> {
+        m.args.get(idx).map_or(Ok(0), |e| self.int(e))
+    }
+
+    fn arg_text(&self, m: &Macro, idx: usize) -> Result<String, XivError> {
+        m.args.get(idx).map_or(Ok(String::new()), |e| self.text(e))
+    }
+
+    fn sheet_text(
+        &self,
+        sheet: &str,
+        row: u32,
+        column: u32,
+        params: Vec<u32>,
+    ) -> Result<String, XivError> {
+        match self.ctx.sheet_value(sheet, row, column)? {
+            Some(Value::String(s)) => Ok(s.into()),
+            Some(Value::SeString(s)) => {
+                if self.depth >= MAX_SHEET_DEPTH {
+                    return Err(XivError::SeStringDepth(sheet.into(), row));
+                }
+                let ctx = ScopedContext {
+                    parent: self.ctx,
+                    integer_params: params,
+                };
+                let depth = self.depth + 1;
+                Evaluator { ctx: &ctx, depth }.string(&s)
+            }
+            Some(Value::Bool(v)) => Ok(u8::from(v).to_string()),
+            Some(other) => Ok(other.to_string()),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn eval_macro(&self, m: &Macro, out: &mut String) -> Result<(), XivError> {
+        match m.code {
+            MacroCode::NewLine => out.push('\n'),
+            MacroCode::NonBreakingSpace => out.push('\u{a0}'),
+            MacroCode::Hyphen => out.push('-'),
+            MacroCode::If => {
+                let branch = if self.arg_int(m, 0)? != 0 { 1 } else { 2 };
+                out.push_str(&self.arg_text(m, branch)?);
+            }
+            MacroCode::Switch => {
+                // cases are 1-based
+                let case = self.arg_int(m, 0)? as usize;
+                if case > 0 {
+                    out.push_str(&self.arg_text(m, case)?);
+                }
+            }
+            MacroCode::IfPcGender => {
+                let branch = match self.ctx.object_gender(self.arg_int(m, 0)?) {
+                    Gender::Male => 1,
+                    Gender::Female => 2,
+                };
+                out.push_str(&self.arg_text(m, branch)?);
+            }
+            MacroCode::IfPcName => {
+                let name = self.ctx.object_name(self.arg_int(m, 0)?);
+                let branch = if name == self.arg_text(m, 1)? { 2 } else { 3 };
+                out.push_str(&self.arg_text(m, branch)?);
+            }
+            MacroCode::IfSelf => {
+                let branch = if self.ctx.is_self(self.arg_int(m, 0)?) {
+                    1
+                } else {
+                    2
+                };
+                out.push_str(&self.arg_text(m, branch)?);
+            }
+            MacroCode::PcName => out.push_str(&self.ctx.object_name(self.arg_int(m, 0)?)),
+            MacroCode::Josa | MacroCode::Josaro => {
+                let word = self.arg_text(m, 0)?;
+                let batchim = word.chars().last().and_then(|c| match c as u32 {
+                    c @ 0xAC00..=0xD7A3 => Some((c - 0xAC00) % 28),
+                    _ => None,
+                });
+                // "ro" particle treats final ㄹ the same way as no final consonant
+                let has_batchim = !matches!(
+                    (m.code, batchim),
+                    (_, None | Some(0)) | (MacroCode::Josaro, Some(8))
+                );
+                out.push_str(&word);
+                out.push_str(&self.arg_text(m, if has_batchim { 1 } else { 2 })?);
+            }
+            MacroCode::Num => out.push_str(&self.arg_int(m, 0)?.to_string()),
+            MacroCode::Hex => out.push_str(&format!("0x{:08X}", self.arg_int(m, 0)?)),
+            MacroCode::Sec => out.push_str(&format!("{:02}", self.arg_int(m, 0)?)),
+            MacroCode::Digit => {
+                let width = self.arg_int(m, 1)? as usize;
+                out.push_str(&format!("{:0width$}", self.arg_int(m, 0)?));
+            }
+            MacroCode::Kilo => {
+                let digits = self.arg_int(m, 0)?.to_string();
+                let separator = self.arg_text(m, 1)?;
+                for (i, c) in digits.chars().enumerate() {
+                    if i > 0 && (digits.len() - i) % 3 == 0 {
+                        out.push_str(&separator);
+                    }
+                    out.push(c);
+                }
+            }
+            MacroCode::Float => {
+                let value = self.arg_int(m, 0)?;
+                let radix = self.arg_int(m, 1)?.max(1);
+                let separator = self.arg_text(m, 2)?;
+                let frac_width = (radix - 1).to_string().len();
+                out.push_str(&format!(
+                    "{}{separator}{:0frac_width$}",
+                    value / radix,
+                    value % radix
+                ));
+            }
+            MacroCode::Ordinal => {
+                let value = self.arg_int(m, 0)?;
+                let suffix = match (value % 10, value % 100) {
+                    (_, 11..=13) => "th",
+                    (1, _) => "st",
+                    (2, _) => "nd",
+                    (3, _) => "rd",
+                    _ => "th",
+                };
+                out.push_str(&format!("{value}{suffix}"));
+            }
+            MacroCode::String => out.push_str(&self.arg_text(m, 0)?),
+            MacroCode::Caps => out.push_str(&self.arg_text(m, 0)?.to_uppercase()),
+            MacroCode::Lower => out.push_str(&self.arg_text(m, 0)?.to_lowercase()),
+            MacroCode::Head => out.push_str(&map_first(&self.arg_text(m, 0)?, char::to_uppercase)),
+            MacroCode::LowerHead => {
+                out.push_str(&map_first(&self.arg_text(m, 0)?, char::to_lowercase))
+            }
+            MacroCode::HeadAll => {
+                let text = self.arg_text(m, 0)?;
+                let words: Vec<_> = text
+                    .split(' ')
+                    .map(|w| map_first(w, char::to_uppercase))
+                    .collect();
+                out.push_str(&words.join(" "));
+            }
+            MacroCode::Split => {
+                let text = self.arg_text(m, 0)?;
+                let separator = self.arg_text(m, 1)?;
+                let index = self.arg_int(m, 2)? as usize;
+                if let Some(part) = index
+                    .checked_sub(1)
+                    .and_then(|i| text.split(separator.as_str()).nth(i))
+                {
+                    out.push_str(part);
+                }
+            }
+            MacroCode::Sheet => {
+                let sheet = self.arg_text(m, 0)?;
+                let row = self.arg_int(m, 1)?;
+                let column = self.arg_int(m, 2)?;
+                let params = (3..m.args.len())
+                    .map(|i| self.arg_int(m, i))
+                    .collect::<Result<_, _>>()?;
+                out.push_str(&self.sheet_text(&sheet, row, column, params)?);
+            }
+            MacroCode::JaNoun
+            | MacroCode::EnNoun
+            | MacroCode::DeNoun
+            | MacroCode::FrNoun
+            | MacroCode::ChNoun => {
+                // (sheet, person, row, amount, case): pick singular or plural column
+                // without article handling
+                let sheet = self.arg_text(m, 0)?;
+                let row = self.arg_int(m, 2)?;
+                let column = match (m.code, self.arg_int(m, 3)?) {
+                    (MacroCode::EnNoun | MacroCode::DeNoun | MacroCode::FrNoun, amount)
+                        if amount != 1 =>
+                    {
+                        2
+                    }
+                    _ => 0,
+                };
+                out.push_str(&self.sheet_text(&sheet, row, column, Vec::new())?);
+            }
+            // styling, links, timing and other macros which produce no text
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn map_first<I: Iterator<Item = char>>(text: &str, f: impl Fn(char) -> I) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => f(first).chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+impl SeString {
+    /// Evaluates macros the way the client does and returns the display text.
+    pub fn evaluate(&self, ctx: &dyn EvalContext) -> Result<String, XivError> {
+        Evaluator { ctx, depth: 0 }.string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Player(Gender);
+
+    impl EvalContext for Player {
+        fn object_name(&self, _object_id: u32) -> String {
+            "Alphinaud".into()
+        }
+        fn player_param(&self, index: u32) -> u32 {
+            if index == 4 {
+                self.0 as u32
+            } else {
+                0
+            }
+        }
+        fn object_gender(&self, _object_id: u32) -> Gender {
+            self.0
+        }
+    }
+
+    #[test]
+    fn evaluate_if() {
+        // <if([gnum4=1],her,his)>
+        let s = SeString::parse(b"\x02\x08\x0F\xE4\xE9\x05\x02\xFF\x04her\xFF\x04his\x03").unwrap();
+        assert_eq!(s.evaluate(&Player(Gender::Male)).unwrap(), "his");
+        assert_eq!(s.evaluate(&Player(Gender::Female)).unwrap(), "her");
+    }
+
+    #[test]
+    fn evaluate_name_and_numbers() {
+        // <pcname(1)>: <kilo(1234567,",")><br><ordinal(22)>
+        let s = SeString::parse(
+            b"\x02\x0A\x02\x02\x03: \x02\x22\x08\xF6\x12\xD6\x87\xFF\x02,\x03\x02\x10\x01\x03\x02\x51\x02\x17\x03",
+        )
+        .unwrap();
+        assert_eq!(
+            s.evaluate(&Player(Gender::Male)).unwrap(),
+            "Alphinaud: 1,234,567\n22nd"
+        );
+    }
+
+    struct Recursive;
+
+    impl EvalContext for Recursive {
+        fn sheet_value(
+            &self,
+            sheet: &str,
+            row: u32,
+            _column: u32,
+        ) -> Result<Option<Value>, XivError> {
+            let markup = format!("{row}:<sheet({sheet},{},0)>", row % 2 + 1);
+            Ok(Some(Value::SeString(SeString::from_markup(&markup)?)))
+        }
+    }
+
+    #[test]
+    fn recursive_sheet_macros() {
+        let s = SeString::from_markup("<sheet(Loop,1,0)>").unwrap();
+        assert!(matches!(
+            s.evaluate(&Recursive),
+            Err(XivError::SeStringDepth(sheet, _)) if &*sheet == "Loop"
+        ));
+    }
+}
@@ -1,115 +1,972 @@
-use std::io;
-use binrw::BinRead;
 use crate::error::XivError;
+use binrw::BinRead;
+use half::f16;
+use rayon::prelude::*;
+use std::io;
 
-fn export_r8(width: u16, height: u16, data: &[u8]) -> Result<image::GrayImage, XivError> {
-    let mut result = image::GrayImage::new(width as u32, height as u32);
+mod channels;
+mod dds;
+mod encode;
+mod ktx2;
 
-    let mut cursor = io::Cursor::new(data);
-    for y in 0..height {
-        for x in 0..width {
-            let l = u8::read_le(&mut cursor).map_err(|_| XivError::TexData)?;
-            result.put_pixel(x as u32, y as u32, [l].into());
-        }
+pub use channels::{split_channels, Channel, TextureKind};
+pub use encode::Compression;
+
+/// Converts every pixel of `IN` bytes into `N` channels of `T`, checking the
+/// data size once up front.
+fn decode_pixels<T: Copy + Default, const IN: usize, const N: usize>(
+    width: u16,
+    height: u16,
+    data: &[u8],
+    convert: impl Fn(&[u8; IN]) -> [T; N],
+) -> Result<Vec<T>, XivError> {
+    let len = width as usize * height as usize;
+    let data = data.get(..len * IN).ok_or(XivError::TexData)?;
+
+    let mut result = vec![T::default(); len * N];
+    let (pixels, _) = data.as_chunks::<IN>();
+    let (out, _) = result.as_chunks_mut::<N>();
+    for (dst, src) in out.iter_mut().zip(pixels) {
+        *dst = convert(src);
     }
-    Ok(result.into())
+    Ok(result)
+}
+
+fn export_r8(width: u16, height: u16, data: &[u8]) -> Result<image::GrayImage, XivError> {
+    let len = width as usize * height as usize;
+    let decoded = data.get(..len).ok_or(XivError::TexData)?.to_vec();
+    image::GrayImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
+
+fn export_b4g4r4a4(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
+    let decoded = decode_pixels(width, height, data, |p: &[u8; 2]| {
+        let p = u16::from_le_bytes(*p);
+        let b = ((p & 0b1111) * 17) as u8;
+        let g = ((p >> 4 & 0b1111) * 17) as u8;
+        let r = ((p >> 8 & 0b1111) * 17) as u8;
+        let a = ((p >> 12 & 0b1111) * 17) as u8;
+        [r, g, b, a]
+    })?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
 }
 
 fn export_b5g5r5a1(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
-    let mut result = image::RgbaImage::new(width as u32, height as u32);
+    let decoded = decode_pixels(width, height, data, |p: &[u8; 2]| {
+        let p = u16::from_le_bytes(*p);
+        let b = ((p & 0b11111) * 8) as u8;
+        let g = ((p >> 5 & 0b11111) * 8) as u8;
+        let r = ((p >> 10 & 0b11111) * 8) as u8;
+        let a = ((p >> 15 & 1) * 255) as u8;
+        [r, g, b, a]
+    })?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
 
-    let mut cursor = io::Cursor::new(data);
-    for y in 0..height {
-        for x in 0..width {
-            let p = u16::read_le(&mut cursor).map_err(|_| XivError::TexData)?;
+fn export_b8g8r8a8(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
+    let decoded = decode_pixels(width, height, data, |&[b, g, r, a]: &[u8; 4]| [r, g, b, a])?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
 
-            let b = ((p & 0b11111) * 8) as u8;
-            let g = ((p >> 5 & 0b11111) * 8) as u8;
-            let r = ((p >> 10 & 0b11111) * 8) as u8;
-            let a = ((p >> 15 & 1) * 255) as u8;
+fn export_b8g8r8x8(width: u16, height: u16, data: &[u8]) -> Result<image::RgbImage, XivError> {
+    let decoded = decode_pixels(width, height, data, |&[b, g, r, _]: &[u8; 4]| [r, g, b])?;
+    image::RgbImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
 
-            result.put_pixel(x as u32, y as u32, [r, g, b, a].into());
-        }
-    }
-    Ok(result.into())
+fn export_d16(
+    width: u16,
+    height: u16,
+    data: &[u8],
+) -> Result<image::ImageBuffer<image::Luma<u16>, Vec<u16>>, XivError> {
+    let decoded = decode_pixels(width, height, data, |p: &[u8; 2]| [u16::from_le_bytes(*p)])?;
+    image::ImageBuffer::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
 }
 
-fn export_r8g8b8a8(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
-    let mut result = image::RgbaImage::new(width as u32, height as u32);
+/// Reads `N` half floats from the start of a pixel.
+fn halves<const N: usize>(p: &[u8]) -> [f32; N] {
+    std::array::from_fn(|i| f16::from_le_bytes([p[i * 2], p[i * 2 + 1]]).to_f32())
+}
 
-    let mut cursor = io::Cursor::new(data);
-    for y in 0..height {
-        for x in 0..width {
-            let r = u8::read_le(&mut cursor).map_err(|_| XivError::TexData)?;
-            let g = u8::read_le(&mut cursor).map_err(|_| XivError::TexData)?;
-            let b = u8::read_le(&mut cursor).map_err(|_| XivError::TexData)?;
-            let a = u8::read_le(&mut cursor).map_err(|_| XivError::TexData)?;
+/// Reads `N` floats from the start of a pixel.
+fn floats<const N: usize>(p: &[u8]) -> [f32; N] {
+    std::array::from_fn(|i| {
+        f32::from_le_bytes([p[i * 4], p[i * 4 + 1], p[i * 4 + 2], p[i * 4 + 3]])
+    })
+}
 
-            result.put_pixel(x as u32, y as u32, [r, g, b, a].into());
-        }
-    }
-    Ok(result.into())
+/// Exports float formats with pixels of `IN` bytes, `convert` fills missing
+/// green and blue channels with zero and missing alpha with one.
+fn export_float<const IN: usize>(
+    width: u16,
+    height: u16,
+    data: &[u8],
+    convert: impl Fn(&[u8; IN]) -> [f32; 4],
+) -> Result<image::Rgba32FImage, XivError> {
+    let decoded = decode_pixels(width, height, data, convert)?;
+    image::Rgba32FImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
 }
 
-fn export_bc(fmt: texpresso::Format, width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
-    let mut decoded = vec![0u8; width as usize * height as usize * 4];
-    fmt.decompress(&data, width as usize, height as usize, &mut decoded);
-    export_r8g8b8a8(width, height, &decoded)
+/// Decodes 4x4 blocks of `BLOCK_LEN` bytes into `N` channels of `T` per pixel,
+/// cropping blocks at the right and bottom edges to the image size. Rows of
+/// blocks are decoded in parallel.
+fn decode_blocks<T: Copy + Default + Send, const BLOCK_LEN: usize, const N: usize>(
+    width: u16,
+    height: u16,
+    data: &[u8],
+    decode: impl Fn(&[u8], &mut [T], usize) + Sync,
+) -> Result<Vec<T>, XivError> {
+    let (w, h) = (width as usize, height as usize);
+    let row_len = w.div_ceil(4) * BLOCK_LEN;
+    let data = data
+        .get(..row_len * h.div_ceil(4))
+        .ok_or(XivError::TexData)?;
+
+    let mut result = vec![T::default(); w * h * N];
+    let pitch = w * N;
+    result
+        .par_chunks_mut((pitch * 4).max(1))
+        .zip(data.par_chunks_exact(row_len.max(1)))
+        .for_each(|(rows, blocks)| {
+            let row_count = rows.len() / pitch;
+            let mut block = vec![T::default(); 16 * N];
+            for (bx, compressed) in blocks.chunks_exact(BLOCK_LEN).enumerate() {
+                let x = bx * 4;
+                // whole blocks go straight into the output
+                if row_count == 4 && x + 4 <= w {
+                    decode(compressed, &mut rows[x * N..], pitch);
+                    continue;
+                }
+                decode(compressed, &mut block, 4 * N);
+                let cols = (w - x).min(4) * N;
+                for row in 0..row_count {
+                    let start = row * pitch + x * N;
+                    rows[start..start + cols].copy_from_slice(&block[row * 4 * N..][..cols]);
+                }
+            }
+        });
+    Ok(result)
 }
 
 fn export_dxt1(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
-    export_bc(texpresso::Format::Bc1, width, height, data)
+    let decoded = decode_blocks::<u8, 8, 4>(width, height, data, bcdec_rs::bc1)?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
 }
 
 fn export_dxt3(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
-    export_bc(texpresso::Format::Bc2, width, height, data)
+    let decoded = decode_blocks::<u8, 16, 4>(width, height, data, bcdec_rs::bc2)?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
 }
 
 fn export_dxt5(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
-    export_bc(texpresso::Format::Bc3, width, height, data)
+    let decoded = decode_blocks::<u8, 16, 4>(width, height, data, bcdec_rs::bc3)?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
+
+fn export_bc4(width: u16, height: u16, data: &[u8]) -> Result<image::GrayImage, XivError> {
+    let decoded = decode_blocks::<u8, 8, 1>(width, height, data, |block, out, pitch| {
+        bcdec_rs::bc4(block, out, pitch, false)
+    })?;
+    image::GrayImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
+
+fn export_bc5(width: u16, height: u16, data: &[u8]) -> Result<image::RgbImage, XivError> {
+    let decoded = decode_blocks::<u8, 16, 2>(width, height, data, |block, out, pitch| {
+        bcdec_rs::bc5(block, out, pitch, false)
+    })?;
+    let rgb = decoded
+        .chunks_exact(2)
+        .flat_map(|rg| [rg[0], rg[1], 0])
+        .collect();
+    image::RgbImage::from_raw(width as u32, height as u32, rgb).ok_or(XivError::TexData)
+}
+
+fn export_bc6h(width: u16, height: u16, data: &[u8]) -> Result<image::Rgba32FImage, XivError> {
+    let decoded = decode_blocks::<f32, 16, 3>(width, height, data, |block, out, pitch| {
+        bcdec_rs::bc6h_float(block, out, pitch, false)
+    })?;
+    let rgba = decoded
+        .chunks_exact(3)
+        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 1.0])
+        .collect();
+    image::Rgba32FImage::from_raw(width as u32, height as u32, rgba).ok_or(XivError::TexData)
+}
+
+fn export_bc7(width: u16, height: u16, data: &[u8]) -> Result<image::RgbaImage, XivError> {
+    let decoded = decode_blocks::<u8, 16, 4>(width, height, data, |block, out, pitch| {
+        bcdec_rs::bc7(block, out, pitch)
+    })?;
+    image::RgbaImage::from_raw(width as u32, height as u32, decoded).ok_or(XivError::TexData)
+}
+
+/// Pixel format of a texture, as stored in .tex header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TexFormat {
+    L8,
+    A8,
+    A4R4G4B4,
+    B5G5R5A1,
+    B8G8R8A8,
+    X8R8G8B8,
+    R32F,
+    G16R16F,
+    G32R32F,
+    A16B16G16R16F,
+    A32B32G32R32F,
+    Dxt1,
+    Dxt3,
+    Dxt5,
+    D16,
+    Bc4,
+    Bc5,
+    Bc6h,
+    Bc7,
+    /// Format which can't be decoded, with its id as stored in .tex header
+    Unknown(u32),
+}
+
+/// Every format `Image` can be decoded from, along with its id.
+const FORMATS: [(TexFormat, u32); 19] = [
+    (TexFormat::L8, 4400),
+    (TexFormat::A8, 4401),
+    (TexFormat::A4R4G4B4, 5184),
+    (TexFormat::B5G5R5A1, 5185),
+    (TexFormat::B8G8R8A8, 5200),
+    (TexFormat::X8R8G8B8, 5201),
+    (TexFormat::R32F, 8528),
+    (TexFormat::G16R16F, 8784),
+    (TexFormat::G32R32F, 8800),
+    (TexFormat::A16B16G16R16F, 9312),
+    (TexFormat::A32B32G32R32F, 9328),
+    (TexFormat::Dxt1, 13344),
+    (TexFormat::Dxt3, 13360),
+    (TexFormat::Dxt5, 13361),
+    (TexFormat::D16, 16704),
+    (TexFormat::Bc4, 24864),
+    (TexFormat::Bc5, 25136),
+    (TexFormat::Bc6h, 25392),
+    (TexFormat::Bc7, 25650),
+];
+
+impl From<u32> for TexFormat {
+    fn from(id: u32) -> Self {
+        FORMATS
+            .iter()
+            .find(|(_, known)| *known == id)
+            .map_or(Self::Unknown(id), |(format, _)| *format)
+    }
+}
+
+impl From<TexFormat> for u32 {
+    fn from(format: TexFormat) -> Self {
+        match format {
+            TexFormat::Unknown(id) => id,
+            format => FORMATS.iter().find(|(f, _)| *f == format).unwrap().1,
+        }
+    }
+}
+
+/// Size of .tex header, mip level data follows right after it.
+const HEADER_LEN: u32 = 80;
+const MAX_MIP_LEVELS: usize = 13;
+
+/// Size in bytes of a single `width`×`height` surface.
+fn surface_size(format: TexFormat, width: u16, height: u16) -> Result<usize, XivError> {
+    use TexFormat::*;
+
+    let (w, h) = (width as usize, height as usize);
+    let blocks = w.div_ceil(4) * h.div_ceil(4);
+    Ok(match format {
+        L8 | A8 => w * h,
+        A4R4G4B4 | B5G5R5A1 | D16 => w * h * 2,
+        B8G8R8A8 | X8R8G8B8 | R32F | G16R16F => w * h * 4,
+        G32R32F | A16B16G16R16F => w * h * 8,
+        A32B32G32R32F => w * h * 16,
+        Dxt1 | Bc4 => blocks * 8,
+        Dxt3 | Dxt5 | Bc5 | Bc6h | Bc7 => blocks * 16,
+        Unknown(id) => return Err(XivError::TexFormat(id)),
+    })
+}
+
+/// Whether a format decodes into `Rgba32F` images.
+fn is_float(format: TexFormat) -> bool {
+    use TexFormat::*;
+
+    matches!(
+        format,
+        R32F | G16R16F | G32R32F | A16B16G16R16F | A32B32G32R32F | Bc6h
+    )
+}
+
+/// Decodes a single surface. Float formats are decoded into `Rgba32F`
+/// images, depth ones into `Luma16`.
+fn decode(format: TexFormat, w: u16, h: u16, data: &[u8]) -> Result<image::DynamicImage, XivError> {
+    use TexFormat::*;
+
+    let r = |[r]: [f32; 1]| [r, 0.0, 0.0, 1.0];
+    let rg = |[r, g]: [f32; 2]| [r, g, 0.0, 1.0];
+
+    match format {
+        L8 | A8 => export_r8(w, h, data).map(From::from),
+        A4R4G4B4 => export_b4g4r4a4(w, h, data).map(From::from),
+        B5G5R5A1 => export_b5g5r5a1(w, h, data).map(From::from),
+        B8G8R8A8 => export_b8g8r8a8(w, h, data).map(From::from),
+        X8R8G8B8 => export_b8g8r8x8(w, h, data).map(From::from),
+        R32F => export_float(w, h, data, |p: &[u8; 4]| r(floats(p))).map(From::from),
+        G16R16F => export_float(w, h, data, |p: &[u8; 4]| rg(halves(p))).map(From::from),
+        G32R32F => export_float(w, h, data, |p: &[u8; 8]| rg(floats(p))).map(From::from),
+        A16B16G16R16F => export_float(w, h, data, |p: &[u8; 8]| halves(p)).map(From::from),
+        A32B32G32R32F => export_float(w, h, data, |p: &[u8; 16]| floats(p)).map(From::from),
+        Dxt1 => export_dxt1(w, h, data).map(From::from),
+        Dxt3 => export_dxt3(w, h, data).map(From::from),
+        Dxt5 => export_dxt5(w, h, data).map(From::from),
+        D16 => export_d16(w, h, data).map(From::from),
+        Bc4 => export_bc4(w, h, data).map(From::from),
+        Bc5 => export_bc5(w, h, data).map(From::from),
+        Bc6h => export_bc6h(w, h, data).map(From::from),
+        Bc7 => export_bc7(w, h, data).map(From::from),
+        Unknown(id) => Err(XivError::TexFormat(id)),
+    }
+}
+
+/// Attribute flags of a texture, the first field of .tex header.
+#[derive(BinRead, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[br(little)]
+pub struct TexAttributes(pub u32);
+
+impl TexAttributes {
+    pub const DISCARD_PER_FRAME: Self = Self(0x1);
+    pub const DISCARD_PER_MAP: Self = Self(0x2);
+    pub const MANAGED: Self = Self(0x4);
+    pub const USER_MANAGED: Self = Self(0x8);
+    pub const CPU_READ: Self = Self(0x10);
+    pub const LOCATION_MAIN: Self = Self(0x20);
+    pub const NO_GPU_READ: Self = Self(0x40);
+    pub const ALIGNED_SIZE: Self = Self(0x80);
+    pub const EDGE_CULLING: Self = Self(0x100);
+    pub const LOCATION_ONION: Self = Self(0x200);
+    pub const READ_WRITE: Self = Self(0x400);
+    pub const IMMUTABLE: Self = Self(0x800);
+    pub const TEXTURE_RENDER_TARGET: Self = Self(0x10_0000);
+    pub const TEXTURE_DEPTH_STENCIL: Self = Self(0x20_0000);
+    pub const TEXTURE_TYPE_1D: Self = Self(0x40_0000);
+    pub const TEXTURE_TYPE_2D: Self = Self(0x80_0000);
+    pub const TEXTURE_TYPE_3D: Self = Self(0x100_0000);
+    pub const TEXTURE_TYPE_CUBE: Self = Self(0x200_0000);
+    pub const TEXTURE_SWIZZLE: Self = Self(0x400_0000);
+    pub const TEXTURE_NO_TILED: Self = Self(0x800_0000);
+    pub const TEXTURE_TYPE_2D_ARRAY: Self = Self(0x1000_0000);
+    /// Data is stored as is, without swizzling.
+    pub const TEXTURE_NO_SWIZZLE: Self = Self(0x8000_0000);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Dimensionality of the texture, `None` if no type flag is set.
+    pub fn texture_type(self) -> Option<TextureType> {
+        [
+            (Self::TEXTURE_TYPE_CUBE, TextureType::Cube),
+            (Self::TEXTURE_TYPE_3D, TextureType::D3),
+            (Self::TEXTURE_TYPE_2D_ARRAY, TextureType::D2Array),
+            (Self::TEXTURE_TYPE_2D, TextureType::D2),
+            (Self::TEXTURE_TYPE_1D, TextureType::D1),
+        ]
+        .into_iter()
+        .find(|(flag, _)| self.contains(*flag))
+        .map(|(_, texture_type)| texture_type)
+    }
+}
+
+impl std::ops::BitOr for TexAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureType {
+    D1,
+    D2,
+    D3,
+    Cube,
+    D2Array,
 }
 
 pub type ImageData = Box<[u8]>;
 
+/// Header of .tex file as stored on disk, mip level count and offsets of
+/// which `Image` derives from its data instead.
+#[binrw::binread]
+#[br(little)]
+pub(crate) struct TexHeader {
+    attributes: TexAttributes,
+    #[br(map = |id: u32| TexFormat::from(id))]
+    format: TexFormat,
+    width: u16,
+    height: u16,
+    depth: u16,
+    #[br(temp)]
+    mip_byte: u8,
+    /// Mip level count, stored in the low 7 bits of its byte.
+    #[br(calc = mip_byte & 0x7F)]
+    mip_levels: u8,
+    #[br(calc = mip_byte & 0x80 != 0)]
+    mip_flag: bool,
+    array_size: u8,
+    lod_offsets: [u32; 3],
+    /// Offsets of mip levels from the start of .tex file, 0 past the last one.
+    mip_offsets: [u32; MAX_MIP_LEVELS],
+}
+
+/// Texture as described by .tex header, along with data of its mip levels.
 pub struct Image {
-    pub format: u32,
+    pub attributes: TexAttributes,
+    pub format: TexFormat,
     pub width: u16,
     pub height: u16,
-    pub layers: u16,
-    pub count: u16,
+    /// Depth of 3D textures, 1 otherwise.
+    pub depth: u16,
+    /// Flag of unknown meaning in the high bit of mip level count.
+    pub mip_flag: bool,
+    /// Layer count of texture arrays, 0 or 1 otherwise.
+    pub array_size: u8,
+    lod_offsets: [u32; 3],
+    /// Data of each mip level, holding every layer, face and depth slice.
     pub mipmaps: Box<[ImageData]>,
 }
 
+/// Single 2D surface of an image: one depth slice of one cube face of one
+/// array layer of one mip level.
+#[derive(Debug, Clone)]
+pub struct Subresource<'a> {
+    pub mip: usize,
+    pub layer: usize,
+    /// Cube face in `+X, -X, +Y, -Y, +Z, -Z` order, 0 for other textures.
+    pub face: usize,
+    /// Depth slice of 3D textures, 0 for other textures.
+    pub slice: usize,
+    pub width: u16,
+    pub height: u16,
+    /// Byte offset of the surface within data of its mip level.
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+impl Subresource<'_> {
+    pub fn export(&self, format: TexFormat) -> Result<image::DynamicImage, XivError> {
+        decode(format, self.width, self.height, self.data)
+    }
+}
+
+/// Offsets of mip levels within .tex file.
+fn mip_offsets(mipmaps: &[ImageData]) -> [u32; MAX_MIP_LEVELS] {
+    let mut offsets = [0; MAX_MIP_LEVELS];
+    let mut offset = HEADER_LEN;
+    for (slot, data) in offsets.iter_mut().zip(mipmaps) {
+        *slot = offset;
+        offset += data.len() as u32;
+    }
+    offsets
+}
+
 impl Image {
+    /// Creates an image out of its mip levels, filling the rest of header
+    /// with defaults.
+    pub fn new(
+        attributes: TexAttributes,
+        format: TexFormat,
+        width: u16,
+        height: u16,
+        depth: u16,
+        array_size: u8,
+        mipmaps: Box<[ImageData]>,
+    ) -> Self {
+        Image {
+            attributes,
+            format,
+            width,
+            height,
+            depth,
+            mip_flag: false,
+            array_size,
+            lod_offsets: [0, 1, 2],
+            mipmaps,
+        }
+    }
+
+    /// Creates an image out of .tex header and data of its mip levels.
+    pub(crate) fn from_header(header: &TexHeader, mipmaps: Box<[ImageData]>) -> Self {
+        Image {
+            attributes: header.attributes,
+            format: header.format,
+            width: header.width,
+            height: header.height,
+            depth: header.depth,
+            mip_flag: header.mip_flag,
+            array_size: header.array_size,
+            lod_offsets: header.lod_offsets,
+            mipmaps,
+        }
+    }
+
+    /// Reads a loose .tex file, as stored on disk by mod tools like Penumbra.
+    pub fn from_reader(mut reader: impl io::Read) -> Result<Image, XivError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(XivError::IO)?;
+        let header = TexHeader::read(&mut io::Cursor::new(&data)).map_err(XivError::TexHeader)?;
+        let mut image = Image::from_header(&header, Box::default());
+
+        let mut mipmaps = Vec::with_capacity(header.mip_levels as usize);
+        for mip in 0..header.mip_levels as usize {
+            let start = *header.mip_offsets.get(mip).ok_or(XivError::TexData)? as usize;
+            let len = image.mip_len(mip)?;
+            let mipmap = data.get(start..start + len).ok_or(XivError::TexData)?;
+            mipmaps.push(mipmap.into());
+        }
+        image.mipmaps = mipmaps.into();
+        Ok(image)
+    }
+
+    /// Writes the image as a loose .tex file, see `to_tex`.
+    pub fn write_to(&self, mut writer: impl io::Write) -> Result<(), XivError> {
+        writer.write_all(&self.to_tex()?).map_err(XivError::IO)
+    }
+
+    pub fn mip_levels(&self) -> usize {
+        self.mipmaps.len()
+    }
+
+    /// Offsets of mip levels from the start of .tex file, 0 past the last one.
+    pub fn mip_offsets(&self) -> [u32; MAX_MIP_LEVELS] {
+        mip_offsets(&self.mipmaps)
+    }
+
+    /// First mip level used by each of three LODs, clamped to the last one.
+    pub fn lod_offsets(&self) -> [u32; 3] {
+        let last_mip = self.mipmaps.len().saturating_sub(1) as u32;
+        self.lod_offsets.map(|lod| lod.min(last_mip))
+    }
+
+    pub fn is_cube(&self) -> bool {
+        self.attributes.contains(TexAttributes::TEXTURE_TYPE_CUBE)
+    }
+
+    pub fn is_3d(&self) -> bool {
+        self.attributes.contains(TexAttributes::TEXTURE_TYPE_3D)
+    }
+
+    /// Layer count of texture arrays, 1 for other textures.
+    pub fn layer_count(&self) -> usize {
+        self.array_size.max(1) as usize
+    }
+
+    pub fn face_count(&self) -> usize {
+        if self.is_cube() {
+            6
+        } else {
+            1
+        }
+    }
+
+    /// Size of a mip level, as `(width, height, depth)`.
+    pub fn mip_size(&self, mip: usize) -> (u16, u16, u16) {
+        let depth = if self.is_3d() { self.depth } else { 1 };
+        let shrink = |v: u16| v.checked_shr(mip as u32).unwrap_or(0).max(1);
+        (shrink(self.width), shrink(self.height), shrink(depth))
+    }
+
+    /// Size in bytes of a mip level with all of its surfaces.
+    fn mip_len(&self, mip: usize) -> Result<usize, XivError> {
+        let (width, height, depth) = self.mip_size(mip);
+        let surfaces = self.layer_count() * self.face_count() * depth as usize;
+        Ok(surface_size(self.format, width, height)? * surfaces)
+    }
+
+    /// Splits every mip level into its surfaces, ordered by mip level, then
+    /// array layer, then cube face, then depth slice.
+    pub fn subresources(&self) -> Result<Vec<Subresource<'_>>, XivError> {
+        let mut result = Vec::new();
+        for (mip, data) in self.mipmaps.iter().enumerate() {
+            let (width, height, depth) = self.mip_size(mip);
+            let size = surface_size(self.format, width, height)?;
+
+            let mut offset = 0;
+            for layer in 0..self.layer_count() {
+                for face in 0..self.face_count() {
+                    for slice in 0..depth as usize {
+                        let data = data.get(offset..offset + size).ok_or(XivError::TexData)?;
+                        result.push(Subresource {
+                            mip,
+                            layer,
+                            face,
+                            slice,
+                            width,
+                            height,
+                            offset,
+                            data,
+                        });
+                        offset += size;
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Decodes the first surface of the first mip level.
     pub fn export(&self) -> Result<image::DynamicImage, XivError> {
-        const L8: u32 = 4400;
-        const A8: u32 = 4401;
-        // const A4R4G4B4: u32 = 5184;
-        const B5G5R5A1: u32 = 5185;
-        const R8G8B8A8: u32 = 5200;
-        // const X8R8G8B8: u32 = 5201;
-        // const R32F: u32 = 8528;
-        // const G16R16F: u32 = 8784;
-        // const G32R32F: u32 = 8800;
-        // const A16B16G16R16F: u32 = 9312;
-        // const A32B32G32R32F: u32 = 9328;
-        const DXT1: u32 = 13344;
-        const DXT3: u32 = 13360;
-        const DXT5: u32 = 13361;
-        // const D16: u32 = 16704;
-
-        let w = self.width;
-        let h = self.height;
         let data = self.mipmaps.first().ok_or(XivError::TexData)?;
+        decode(self.format, self.width, self.height, data)
+    }
+
+    /// Decodes every surface into one image, with a row per mip level and a
+    /// column per array layer, cube face and depth slice.
+    ///
+    /// Float formats are laid out into an `Rgba32F` image, others into `Rgba8`.
+    pub fn contact_sheet(&self) -> Result<image::DynamicImage, XivError> {
+        let subresources = self.subresources()?;
+        let columns = subresources.iter().take_while(|s| s.mip == 0).count() as u32;
+        let mut rows = Vec::new();
+        for s in &subresources {
+            if rows.last().is_none_or(|(mip, _)| *mip != s.mip) {
+                rows.push((s.mip, s.height as u32));
+            }
+        }
+        let width = columns * self.width as u32;
+        let height = rows.iter().map(|(_, h)| h).sum();
+
+        let mut sheet = match is_float(self.format) {
+            true => image::DynamicImage::new_rgba32f(width, height),
+            false => image::DynamicImage::new_rgba8(width, height),
+        };
+        let mut y = 0;
+        for (mip, row_height) in rows {
+            let cells = subresources.iter().filter(|s| s.mip == mip);
+            for (column, s) in cells.enumerate() {
+                let x = column as i64 * s.width as i64;
+                match (&mut sheet, s.export(self.format)?) {
+                    (image::DynamicImage::ImageRgba32F(sheet), cell) => {
+                        image::imageops::replace(sheet, &cell.into_rgba32f(), x, y)
+                    }
+                    (sheet, cell) => image::imageops::replace(sheet, &cell.into_rgba8(), x, y),
+                }
+            }
+            y += row_height as i64;
+        }
+        Ok(sheet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match self.format {
-            L8 | A8 => export_r8(w, h, &data).map(From::from),
-            B5G5R5A1 => export_b5g5r5a1(w, h, &data).map(From::from),
-            R8G8B8A8 => export_r8g8b8a8(w, h, &data).map(From::from),
-            DXT1 => export_dxt1(w, h, &data).map(From::from),
-            DXT3 => export_dxt3(w, h, &data).map(From::from),
-            DXT5 => export_dxt5(w, h, &data).map(From::from),
-            _ => Err(XivError::TexFormat(self.format)),
+    fn image(format: impl Into<TexFormat>, width: u16, height: u16, data: Vec<u8>) -> Image {
+        Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            format.into(),
+            width,
+            height,
+            1,
+            1,
+            vec![data.into_boxed_slice()].into_boxed_slice(),
+        )
+    }
+
+    /// Packs `(value, bits)` fields into a block, least significant bit first.
+    fn pack_bits(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut block = 0u128;
+        let mut pos = 0;
+        for (value, bits) in fields {
+            block |= (*value as u128) << pos;
+            pos += bits;
         }
+        block.to_le_bytes().to_vec()
+    }
+
+    #[test]
+    fn uncompressed_formats() {
+        let img = image(5184, 1, 1, vec![0x21, 0xF3]).export().unwrap();
+        assert_eq!(img.to_rgba8().get_pixel(0, 0).0, [0x33, 0x22, 0x11, 0xFF]);
+
+        let img = image(5200, 2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8])
+            .export()
+            .unwrap();
+        assert_eq!(img.to_rgba8().get_pixel(1, 0).0, [7, 6, 5, 8]);
+
+        let img = image(5201, 1, 1, vec![1, 2, 3, 0]).export().unwrap();
+        assert_eq!(img.to_rgba8().get_pixel(0, 0).0, [3, 2, 1, 255]);
+
+        let img = image(16704, 1, 1, 0x1234u16.to_le_bytes().to_vec())
+            .export()
+            .unwrap();
+        assert_eq!(img.as_luma16().unwrap().get_pixel(0, 0).0, [0x1234]);
+
+        assert!(matches!(
+            image(5200, 2, 2, vec![0; 12]).export(),
+            Err(XivError::TexData)
+        ));
+        assert!(matches!(
+            image(1, 1, 1, vec![0]).export(),
+            Err(XivError::TexFormat(1))
+        ));
+    }
+
+    #[test]
+    fn float_formats() {
+        let img = image(8528, 1, 1, 0.5f32.to_le_bytes().to_vec())
+            .export()
+            .unwrap();
+        assert_eq!(
+            img.as_rgba32f().unwrap().get_pixel(0, 0).0,
+            [0.5, 0.0, 0.0, 1.0]
+        );
+
+        let data = [f16::from_f32(0.25), f16::from_f32(-2.0)]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let img = image(8784, 1, 1, data).export().unwrap();
+        assert_eq!(
+            img.as_rgba32f().unwrap().get_pixel(0, 0).0,
+            [0.25, -2.0, 0.0, 1.0]
+        );
+
+        let data = [1.5f32, 2.5].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let img = image(8800, 1, 1, data).export().unwrap();
+        assert_eq!(
+            img.as_rgba32f().unwrap().get_pixel(0, 0).0,
+            [1.5, 2.5, 0.0, 1.0]
+        );
+
+        let data = [0.5, 1.0, 2.0, 0.75]
+            .iter()
+            .flat_map(|v| f16::from_f32(*v).to_le_bytes())
+            .collect();
+        let img = image(9312, 1, 1, data).export().unwrap();
+        assert_eq!(
+            img.as_rgba32f().unwrap().get_pixel(0, 0).0,
+            [0.5, 1.0, 2.0, 0.75]
+        );
+
+        let data = [0.1f32, 0.2, 0.3, 0.4]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let img = image(9328, 1, 1, data).export().unwrap();
+        assert_eq!(
+            img.as_rgba32f().unwrap().get_pixel(0, 0).0,
+            [0.1, 0.2, 0.3, 0.4]
+        );
+    }
+
+    #[test]
+    fn block_compressed_formats() {
+        // single-valued BC4 blocks, cropped to a 5x3 image of 2x1 blocks
+        let data = [[200, 0, 0, 0, 0, 0, 0, 0], [100, 0, 0, 0, 0, 0, 0, 0]].concat();
+        let img = image(24864, 5, 3, data).export().unwrap();
+        let img = img.as_luma8().unwrap();
+        assert_eq!(img.dimensions(), (5, 3));
+        assert_eq!(img.get_pixel(3, 2).0, [200]);
+        assert_eq!(img.get_pixel(4, 0).0, [100]);
+
+        let data = [[10, 0, 0, 0, 0, 0, 0, 0], [20, 0, 0, 0, 0, 0, 0, 0]].concat();
+        let img = image(25136, 4, 4, data).export().unwrap();
+        assert_eq!(img.as_rgb8().unwrap().get_pixel(1, 1).0, [10, 20, 0]);
+
+        // BC6H mode 11 with both endpoints red 495, unquantized to half 1.0
+        let data = pack_bits(&[(0b00011, 5), (495, 10), (0, 20), (495, 10), (0, 20)]);
+        let img = image(25392, 4, 4, data).export().unwrap();
+        assert_eq!(
+            img.as_rgba32f().unwrap().get_pixel(2, 3).0,
+            [1.0, 0.0, 0.0, 1.0]
+        );
+
+        // BC7 mode 6 with both endpoints red and alpha at 127 and P bits unset
+        let endpoint = [
+            (127, 7),
+            (127, 7),
+            (0, 7),
+            (0, 7),
+            (0, 7),
+            (0, 7),
+            (127, 7),
+            (127, 7),
+        ];
+        let data = pack_bits(&[&[(1 << 6, 7)], &endpoint[..]].concat());
+        let img = image(25650, 4, 4, data).export().unwrap();
+        assert_eq!(img.as_rgba8().unwrap().get_pixel(3, 0).0, [254, 0, 0, 254]);
+
+        // BC1 with both colors white
+        let img = image(13344, 4, 4, vec![0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0])
+            .export()
+            .unwrap();
+        assert_eq!(
+            img.as_rgba8().unwrap().get_pixel(0, 0).0,
+            [255, 255, 255, 255]
+        );
+
+        assert!(matches!(
+            image(25650, 8, 4, vec![0; 16]).export(),
+            Err(XivError::TexData)
+        ));
+    }
+
+    #[test]
+    fn partial_blocks() {
+        // 3x3 grid of DXT1 blocks, each of a single color
+        let data: Vec<u8> = (0..9u8)
+            .flat_map(|i| [i * 7, i << 3, i * 7, i << 3, 0, 0, 0, 0])
+            .collect();
+        let mut last = [0u8; 64];
+        bcdec_rs::bc1(&data[64..], &mut last, 16);
+
+        let full = image(TexFormat::Dxt1, 12, 12, data.clone())
+            .export()
+            .unwrap();
+        let cropped = image(TexFormat::Dxt1, 10, 9, data).export().unwrap();
+        assert_eq!(full.to_rgba8().get_pixel(11, 11).0, last[60..]);
+        assert_eq!(
+            cropped.into_rgba8(),
+            full.crop_imm(0, 0, 10, 9).into_rgba8()
+        );
+    }
+
+    #[test]
+    fn cube_subresources() {
+        // 4x2 L8 cubemap with two mip levels, each face filled with its index
+        let mip0: Vec<u8> = (0..6).flat_map(|face| [face; 8]).collect();
+        let mip1: Vec<u8> = (0..6).flat_map(|face| [face + 10; 2]).collect();
+        let img = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D | TexAttributes::TEXTURE_TYPE_CUBE,
+            TexFormat::L8,
+            4,
+            2,
+            1,
+            1,
+            vec![mip0.into(), mip1.into()].into(),
+        );
+
+        let subresources = img.subresources().unwrap();
+        assert_eq!(subresources.len(), 12);
+        let s = &subresources[9];
+        assert_eq!(
+            (s.mip, s.face, s.width, s.height, s.offset),
+            (1, 3, 2, 1, 6)
+        );
+        assert_eq!(s.data, [13, 13]);
+
+        let sheet = img.contact_sheet().unwrap().into_rgba8();
+        assert_eq!(sheet.dimensions(), (24, 3));
+        assert_eq!(sheet.get_pixel(23, 1).0, [5, 5, 5, 255]);
+        assert_eq!(sheet.get_pixel(11, 2).0, [15, 15, 15, 255]);
+        assert_eq!(sheet.get_pixel(12, 2).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn volume_and_array_subresources() {
+        let volume = Image::new(
+            TexAttributes::TEXTURE_TYPE_3D,
+            TexFormat::L8,
+            2,
+            2,
+            4,
+            1,
+            vec![vec![0; 16].into(), vec![0; 2].into()].into(),
+        );
+        let slices: Vec<_> = volume
+            .subresources()
+            .unwrap()
+            .iter()
+            .map(|s| (s.mip, s.slice, s.width))
+            .collect();
+        assert_eq!(
+            slices,
+            [
+                (0, 0, 2),
+                (0, 1, 2),
+                (0, 2, 2),
+                (0, 3, 2),
+                (1, 0, 1),
+                (1, 1, 1)
+            ]
+        );
+
+        let array = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            TexFormat::Bc4,
+            4,
+            4,
+            1,
+            3,
+            vec![vec![0; 16].into()].into(),
+        );
+        assert!(matches!(array.subresources(), Err(XivError::TexData)));
+    }
+
+    #[test]
+    fn loose_file() {
+        let cube = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D | TexAttributes::TEXTURE_TYPE_CUBE,
+            TexFormat::L8,
+            4,
+            2,
+            1,
+            1,
+            vec![(0..48).collect(), (0..12).collect()].into(),
+        );
+        let mut tex = Vec::new();
+        cube.write_to(&mut tex).unwrap();
+        assert_eq!(tex.len(), 80 + 60);
+
+        let read = Image::from_reader(tex.as_slice()).unwrap();
+        assert_eq!(read.attributes, cube.attributes);
+        assert_eq!(
+            (read.format, read.width, read.height),
+            (TexFormat::L8, 4, 2)
+        );
+        assert_eq!(read.mip_offsets(), cube.mip_offsets());
+        assert_eq!(read.mipmaps, cube.mipmaps);
+
+        tex.pop();
+        assert!(matches!(
+            Image::from_reader(tex.as_slice()),
+            Err(XivError::TexData)
+        ));
+    }
+
+    #[test]
+    fn header_fields() {
+        let mut data = Vec::new();
+        data.extend(0x8280_0000u32.to_le_bytes());
+        data.extend(13361u32.to_le_bytes());
+        data.extend([0, 1, 128, 0, 1, 0, 0x80 | 9, 1]);
+        data.extend([0, 1, 2].map(u32::to_le_bytes).concat());
+        data.extend([80, 0x1_0080].map(u32::to_le_bytes).concat());
+        data.extend([0; 11 * 4]);
+
+        let header = TexHeader::read(&mut io::Cursor::new(&data)).unwrap();
+        assert_eq!((header.format, header.mip_levels), (TexFormat::Dxt5, 9));
+        assert_eq!(header.lod_offsets, [0, 1, 2]);
+        assert_eq!(header.mip_offsets[..3], [80, 0x1_0080, 0]);
+
+        // mip level count and offsets follow the data rather than the header
+        let image = Image::from_header(&header, vec![vec![0; 16].into()].into());
+        assert!(image.attributes.contains(TexAttributes::TEXTURE_NO_SWIZZLE));
+        assert_eq!(image.attributes.texture_type(), Some(TextureType::Cube));
+        assert_eq!((image.width, image.height, image.depth), (256, 128, 1));
+        assert_eq!((image.mip_levels(), image.mip_flag), (1, true));
+        assert_eq!((image.array_size, image.layer_count()), (1, 1));
+        assert_eq!(image.lod_offsets(), [0, 0, 0]);
+        assert_eq!(image.mip_offsets()[..2], [80, 0]);
+        assert!(matches!(
+            Image::from_reader(&data[..16]),
+            Err(XivError::TexHeader(_))
+        ));
+
+        let array = TexAttributes::TEXTURE_TYPE_2D_ARRAY;
+        assert_eq!(array.texture_type(), Some(TextureType::D2Array));
+        assert_eq!(TexAttributes::default().texture_type(), None);
     }
 }
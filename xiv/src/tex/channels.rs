@@ -0,0 +1,199 @@
+use image::{DynamicImage, ImageBuffer, Luma, Pixel, Rgb, Rgba};
+
+/// Texture conventions of game materials, told apart by file name suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    /// `_n`: normal X and Y in red and green, opacity in blue, color set
+    /// row in alpha
+    Normal,
+    /// `_m`: specular, roughness and ambient occlusion
+    Mask,
+    /// `_s`: specular color, gloss in alpha
+    Specular,
+    /// `_d`: diffuse color, opacity in alpha
+    Diffuse,
+    /// `_id`: color set row and blend weight
+    Id,
+}
+
+impl TextureKind {
+    /// Guesses texture kind by suffix of file name, e.g. `..._top_n.tex`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let stem = name.split_once('.').map_or(name, |(stem, _)| stem);
+        match stem.rsplit_once('_')?.1.to_ascii_lowercase().as_str() {
+            "n" => Some(Self::Normal),
+            "m" => Some(Self::Mask),
+            "s" => Some(Self::Specular),
+            "d" => Some(Self::Diffuse),
+            "id" => Some(Self::Id),
+            _ => None,
+        }
+    }
+
+    /// Names of red, green, blue and alpha channels, `None` for unused ones.
+    fn channel_names(self) -> [Option<&'static str>; 4] {
+        match self {
+            Self::Normal => [
+                Some("normal_x"),
+                Some("normal_y"),
+                Some("opacity"),
+                Some("colorset"),
+            ],
+            Self::Mask => [Some("specular"), Some("roughness"), Some("occlusion"), None],
+            Self::Specular => [
+                Some("specular_r"),
+                Some("specular_g"),
+                Some("specular_b"),
+                Some("gloss"),
+            ],
+            Self::Diffuse => [Some("red"), Some("green"), Some("blue"), Some("opacity")],
+            Self::Id => [Some("colorset"), Some("blend"), None, None],
+        }
+    }
+}
+
+/// One image split out of a texture, grayscale unless noted otherwise.
+pub struct Channel {
+    pub name: &'static str,
+    pub image: DynamicImage,
+}
+
+/// Splits an image into a grayscale image per channel. With a known texture
+/// kind channels are named after what they hold, and normal maps also get
+/// their Z channel reconstructed, both as grayscale `normal_z` and within
+/// an RGB `normal` image.
+///
+/// 8-bit images are split into 8-bit channels, the rest into 16-bit ones.
+pub fn split_channels(image: &DynamicImage, kind: Option<TextureKind>) -> Vec<Channel> {
+    let color = image.color();
+    let mut names = match (kind, color.has_color()) {
+        (Some(kind), _) => kind.channel_names(),
+        (None, true) => [Some("r"), Some("g"), Some("b"), Some("a")],
+        (None, false) => [Some("l"), None, None, Some("a")],
+    };
+    if !color.has_alpha() {
+        names[3] = None;
+    }
+
+    let eight_bit = color.bytes_per_pixel() == color.channel_count();
+    let mut channels = match eight_bit {
+        true => split(&image.to_rgba8(), names),
+        false => split(&image.to_rgba16(), names),
+    };
+    if kind == Some(TextureKind::Normal) {
+        let (z, normal) = normal_z(&image.to_rgba16());
+        let (z, normal) = match eight_bit {
+            true => (z.to_luma8().into(), normal.to_rgb8().into()),
+            false => (z, normal),
+        };
+        channels.push(Channel {
+            name: "normal_z",
+            image: z,
+        });
+        channels.push(Channel {
+            name: "normal",
+            image: normal,
+        });
+    }
+    channels
+}
+
+fn split<S: Copy>(
+    rgba: &ImageBuffer<Rgba<S>, Vec<S>>,
+    names: [Option<&'static str>; 4],
+) -> Vec<Channel>
+where
+    Rgba<S>: Pixel<Subpixel = S>,
+    Luma<S>: Pixel<Subpixel = S>,
+    DynamicImage: From<ImageBuffer<Luma<S>, Vec<S>>>,
+{
+    let (width, height) = rgba.dimensions();
+    names
+        .into_iter()
+        .enumerate()
+        .filter_map(|(c, name)| {
+            let name = name?;
+            let channel = rgba.pixels().map(|px| px.channels()[c]).collect();
+            let image = ImageBuffer::<Luma<S>, _>::from_raw(width, height, channel)?;
+            Some(Channel {
+                name,
+                image: image.into(),
+            })
+        })
+        .collect()
+}
+
+/// Reconstructs Z of unit normals stored in red and green channels, returns
+/// it as grayscale and along with X and Y as RGB.
+fn normal_z(rgba: &ImageBuffer<Rgba<u16>, Vec<u16>>) -> (DynamicImage, DynamicImage) {
+    let unpack = |v: u16| v as f32 / u16::MAX as f32 * 2.0 - 1.0;
+    let (width, height) = rgba.dimensions();
+    let z: Vec<u16> = rgba
+        .pixels()
+        .map(|px| {
+            let (x, y) = (unpack(px.0[0]), unpack(px.0[1]));
+            let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+            ((z + 1.0) / 2.0 * u16::MAX as f32).round() as u16
+        })
+        .collect();
+    let normal = rgba
+        .pixels()
+        .zip(&z)
+        .flat_map(|(px, z)| [px.0[0], px.0[1], *z])
+        .collect();
+
+    let z = ImageBuffer::<Luma<u16>, _>::from_raw(width, height, z).unwrap();
+    let normal = ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, normal).unwrap();
+    (z.into(), normal.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(channels: &[Channel]) -> Vec<&str> {
+        channels.iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn texture_kinds() {
+        let path = "chara/equipment/e0001/texture/v01_c0101e0001_top_n.tex";
+        assert_eq!(TextureKind::from_path(path), Some(TextureKind::Normal));
+        assert_eq!(TextureKind::from_path("a/b_ID.tex"), Some(TextureKind::Id));
+        assert_eq!(TextureKind::from_path("a_m/b.tex"), None);
+        assert_eq!(TextureKind::from_path("ui/icon/000000/000001.tex"), None);
+    }
+
+    #[test]
+    fn plain_channels() {
+        let rgba = image::RgbaImage::from_pixel(2, 1, Rgba([1, 2, 3, 4]));
+        let channels = split_channels(&rgba.into(), None);
+        assert_eq!(names(&channels), ["r", "g", "b", "a"]);
+        assert_eq!(channels[2].image.as_luma8().unwrap().as_raw(), &[3, 3]);
+
+        let gray = image::GrayImage::from_pixel(1, 1, Luma([9]));
+        assert_eq!(names(&split_channels(&gray.into(), None)), ["l"]);
+
+        let float = image::Rgb32FImage::from_pixel(1, 1, Rgb([0.5, 1.0, 0.0]));
+        let channels = split_channels(&float.into(), Some(TextureKind::Mask));
+        assert_eq!(names(&channels), ["specular", "roughness", "occlusion"]);
+        assert_eq!(channels[1].image.as_luma16().unwrap().as_raw(), &[65535]);
+    }
+
+    #[test]
+    fn normal_reconstruction() {
+        let rgba = image::RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([128, 128, 255, 7]),
+            _ => Rgba([255, 128, 0, 7]),
+        });
+        let channels = split_channels(&rgba.into(), Some(TextureKind::Normal));
+        assert_eq!(
+            names(&channels),
+            ["normal_x", "normal_y", "opacity", "colorset", "normal_z", "normal"]
+        );
+        assert_eq!(channels[4].image.as_luma8().unwrap().as_raw(), &[255, 128]);
+        let normal = channels[5].image.as_rgb8().unwrap();
+        assert_eq!(normal.as_raw(), &[128, 128, 255, 255, 128, 128]);
+    }
+}
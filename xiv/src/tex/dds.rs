@@ -0,0 +1,477 @@
+use super::*;
+use binrw::binread;
+use byteorder::{WriteBytesExt, LE};
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+const DDSD_DEPTH: u32 = 0x80_0000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFE00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+const DIMENSION_TEXTURE2D: u32 = 3;
+const DIMENSION_TEXTURE3D: u32 = 4;
+const MISC_TEXTURECUBE: u32 = 0x4;
+
+/// Legacy pixel format of a DDS file, described without the DX10 header.
+#[derive(PartialEq, Eq)]
+enum DdsFormat {
+    Masks {
+        flags: u32,
+        bits: u32,
+        masks: [u32; 4],
+    },
+    FourCC([u8; 4]),
+}
+
+fn dds_format(format: TexFormat) -> Option<DdsFormat> {
+    use DdsFormat::*;
+    use TexFormat::*;
+
+    let masks = |flags, bits, masks| Masks { flags, bits, masks };
+    Some(match format {
+        L8 => masks(DDPF_LUMINANCE, 8, [0xFF, 0, 0, 0]),
+        A8 => masks(DDPF_ALPHA, 8, [0, 0, 0, 0xFF]),
+        A4R4G4B4 => masks(
+            DDPF_RGB | DDPF_ALPHAPIXELS,
+            16,
+            [0x0F00, 0x00F0, 0x000F, 0xF000],
+        ),
+        B5G5R5A1 => masks(
+            DDPF_RGB | DDPF_ALPHAPIXELS,
+            16,
+            [0x7C00, 0x03E0, 0x001F, 0x8000],
+        ),
+        B8G8R8A8 => masks(
+            DDPF_RGB | DDPF_ALPHAPIXELS,
+            32,
+            [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000],
+        ),
+        X8R8G8B8 => masks(DDPF_RGB, 32, [0xFF_0000, 0xFF00, 0xFF, 0]),
+        Dxt1 => FourCC(*b"DXT1"),
+        Dxt3 => FourCC(*b"DXT3"),
+        Dxt5 => FourCC(*b"DXT5"),
+        Bc4 => FourCC(*b"ATI1"),
+        Bc5 => FourCC(*b"ATI2"),
+        _ => return None,
+    })
+}
+
+/// DXGI format used with the DX10 header.
+fn dxgi_format(format: TexFormat) -> Result<u32, XivError> {
+    use TexFormat::*;
+
+    Ok(match format {
+        L8 => 61,
+        A8 => 65,
+        A4R4G4B4 => 115,
+        B5G5R5A1 => 86,
+        B8G8R8A8 => 87,
+        X8R8G8B8 => 88,
+        R32F => 41,
+        G16R16F => 34,
+        G32R32F => 16,
+        A16B16G16R16F => 10,
+        A32B32G32R32F => 2,
+        Dxt1 => 71,
+        Dxt3 => 74,
+        Dxt5 => 77,
+        // R16_UNORM rather than D16_UNORM, which most tools can't open
+        D16 => 56,
+        Bc4 => 80,
+        Bc5 => 83,
+        Bc6h => 95,
+        Bc7 => 98,
+        Unknown(id) => return Err(XivError::TexFormat(id)),
+    })
+}
+
+/// Finds .tex format of a DDS file, accepting sRGB and typeless variants of
+/// DXGI formats as well.
+fn tex_format(pf: &DdsPixelFormat, dx10: Option<&Dx10Header>) -> Result<TexFormat, XivError> {
+    if let Some(dx10) = dx10 {
+        let dxgi = match dx10.dxgi_format {
+            55 => 56,
+            70 | 72 => 71,
+            73 | 75 => 74,
+            76 | 78 => 77,
+            79 => 80,
+            82 => 83,
+            90 | 91 => 87,
+            92 | 93 => 88,
+            94 => 95,
+            97 | 99 => 98,
+            dxgi => dxgi,
+        };
+        return FORMATS
+            .iter()
+            .map(|(format, _)| *format)
+            .find(|f| dxgi_format(*f).is_ok_and(|d| d == dxgi))
+            .ok_or_else(|| XivError::DdsFormat(format!("DXGI format {dxgi}").into()));
+    }
+
+    let legacy = match &pf.fourcc {
+        b"BC4U" => DdsFormat::FourCC(*b"ATI1"),
+        b"BC5U" => DdsFormat::FourCC(*b"ATI2"),
+        fourcc if pf.flags & DDPF_FOURCC != 0 => DdsFormat::FourCC(*fourcc),
+        _ => DdsFormat::Masks {
+            flags: pf.flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA | DDPF_RGB | DDPF_LUMINANCE),
+            bits: pf.bits,
+            masks: pf.masks,
+        },
+    };
+    FORMATS
+        .iter()
+        .map(|(format, _)| *format)
+        .find(|f| dds_format(*f).as_ref() == Some(&legacy))
+        .ok_or_else(|| match legacy {
+            DdsFormat::FourCC(fourcc) => {
+                let fourcc = String::from_utf8_lossy(&fourcc).into();
+                XivError::DdsFormat(fourcc)
+            }
+            DdsFormat::Masks { bits, masks, .. } => {
+                XivError::DdsFormat(format!("{bits} bit with masks {masks:X?}").into())
+            }
+        })
+}
+
+#[binread]
+#[br(little, magic = b"DDS ")]
+struct DdsHeader {
+    _size: u32,
+    _flags: u32,
+    height: u32,
+    width: u32,
+    _pitch: u32,
+    depth: u32,
+    mip_count: u32,
+    _reserved: [u32; 11],
+    pf: DdsPixelFormat,
+    _caps: u32,
+    caps2: u32,
+    _caps3: [u32; 3],
+    #[br(if(pf.flags & DDPF_FOURCC != 0 && &pf.fourcc == b"DX10"))]
+    dx10: Option<Dx10Header>,
+}
+
+#[binread]
+#[br(little)]
+struct DdsPixelFormat {
+    _size: u32,
+    flags: u32,
+    fourcc: [u8; 4],
+    bits: u32,
+    masks: [u32; 4],
+}
+
+#[binread]
+#[br(little)]
+struct Dx10Header {
+    dxgi_format: u32,
+    dimension: u32,
+    misc: u32,
+    array_size: u32,
+    _misc2: u32,
+}
+
+fn is_block_compressed(format: TexFormat) -> bool {
+    use TexFormat::*;
+
+    matches!(format, Dxt1 | Dxt3 | Dxt5 | Bc4 | Bc5 | Bc6h | Bc7)
+}
+
+impl Image {
+    /// Writes the image into a DDS file, keeping its data as is, with the
+    /// DX10 header for DXGI-only formats and texture arrays.
+    pub fn to_dds(&self) -> Result<Vec<u8>, XivError> {
+        let legacy = match self.layer_count() {
+            1 => dds_format(self.format),
+            _ => None,
+        };
+        let dxgi = match legacy {
+            Some(_) => None,
+            None => Some(dxgi_format(self.format)?),
+        };
+        let mip_count = self.mipmaps.len() as u32;
+        let (_, _, depth) = self.mip_size(0);
+
+        let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+        let pitch = if is_block_compressed(self.format) {
+            flags |= DDSD_LINEARSIZE;
+            surface_size(self.format, self.width, self.height)?
+        } else {
+            flags |= DDSD_PITCH;
+            surface_size(self.format, self.width, 1)?
+        };
+        let mut caps = DDSCAPS_TEXTURE;
+        let mut caps2 = 0;
+        if mip_count > 1 {
+            flags |= DDSD_MIPMAPCOUNT;
+            caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+        }
+        if self.is_cube() {
+            caps |= DDSCAPS_COMPLEX;
+            caps2 |= DDSCAPS2_CUBEMAP_ALL_FACES;
+        }
+        if self.is_3d() {
+            flags |= DDSD_DEPTH;
+            caps |= DDSCAPS_COMPLEX;
+            caps2 |= DDSCAPS2_VOLUME;
+        }
+
+        let mut out = b"DDS ".to_vec();
+        // writing into Vec never fails
+        let w = &mut out;
+        w.write_u32::<LE>(124).unwrap();
+        w.write_u32::<LE>(flags).unwrap();
+        w.write_u32::<LE>(self.height as u32).unwrap();
+        w.write_u32::<LE>(self.width as u32).unwrap();
+        w.write_u32::<LE>(pitch as u32).unwrap();
+        w.write_u32::<LE>(depth as u32).unwrap();
+        w.write_u32::<LE>(mip_count).unwrap();
+        w.extend([0; 11 * 4]);
+
+        w.write_u32::<LE>(32).unwrap();
+        match legacy {
+            None => {
+                w.write_u32::<LE>(DDPF_FOURCC).unwrap();
+                w.extend(b"DX10");
+                w.extend([0; 5 * 4]);
+            }
+            Some(DdsFormat::FourCC(fourcc)) => {
+                w.write_u32::<LE>(DDPF_FOURCC).unwrap();
+                w.extend(fourcc);
+                w.extend([0; 5 * 4]);
+            }
+            Some(DdsFormat::Masks { flags, bits, masks }) => {
+                w.write_u32::<LE>(flags).unwrap();
+                w.write_u32::<LE>(0).unwrap();
+                w.write_u32::<LE>(bits).unwrap();
+                for mask in masks {
+                    w.write_u32::<LE>(mask).unwrap();
+                }
+            }
+        }
+
+        w.write_u32::<LE>(caps).unwrap();
+        w.write_u32::<LE>(caps2).unwrap();
+        w.extend([0; 3 * 4]);
+
+        if let Some(dxgi) = dxgi {
+            let dimension = match self.is_3d() {
+                true => DIMENSION_TEXTURE3D,
+                false => DIMENSION_TEXTURE2D,
+            };
+            let misc = match self.is_cube() {
+                true => MISC_TEXTURECUBE,
+                false => 0,
+            };
+            w.write_u32::<LE>(dxgi).unwrap();
+            w.write_u32::<LE>(dimension).unwrap();
+            w.write_u32::<LE>(misc).unwrap();
+            w.write_u32::<LE>(self.layer_count() as u32).unwrap();
+            w.write_u32::<LE>(0).unwrap();
+        }
+
+        // DDS stores every mip level of a layer or face before the next one
+        let mut subresources = self.subresources()?;
+        subresources.sort_by_key(|s| (s.layer, s.face, s.mip));
+        for s in subresources {
+            out.extend(s.data);
+        }
+        Ok(out)
+    }
+
+    /// Reads a DDS file into an image, keeping its data as is.
+    pub fn from_dds(data: &[u8]) -> Result<Image, XivError> {
+        let mut cursor = io::Cursor::new(data);
+        let header = DdsHeader::read(&mut cursor).map_err(XivError::DdsHeader)?;
+        let format = tex_format(&header.pf, header.dx10.as_ref())?;
+
+        let (width, height) = (header.width, header.height);
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(XivError::TexSize(width, height));
+        };
+        let dx10 = header.dx10.as_ref();
+        let is_cube = header.caps2 & DDSCAPS2_CUBEMAP != 0
+            || dx10.is_some_and(|h| h.misc & MISC_TEXTURECUBE != 0);
+        let is_3d = header.caps2 & DDSCAPS2_VOLUME != 0
+            || dx10.is_some_and(|h| h.dimension == DIMENSION_TEXTURE3D);
+        let array_size = dx10.map_or(1, |h| h.array_size.max(1));
+        let mip_count = header.mip_count.max(1);
+        if mip_count > 13 || array_size > 255 {
+            return Err(XivError::TexData);
+        }
+
+        let mut attributes = match is_3d {
+            true => TexAttributes::TEXTURE_TYPE_3D,
+            false => TexAttributes::TEXTURE_TYPE_2D,
+        };
+        if is_cube {
+            attributes = attributes | TexAttributes::TEXTURE_TYPE_CUBE;
+        }
+        let depth = if is_3d { header.depth.max(1) as u16 } else { 1 };
+        let mipmaps = vec![ImageData::default(); mip_count as usize].into();
+        let mut image = Image::new(
+            attributes,
+            format,
+            width,
+            height,
+            depth,
+            array_size as u8,
+            mipmaps,
+        );
+
+        // DDS stores every mip level of a layer or face before the next one
+        let mut data = &data[cursor.position() as usize..];
+        let mut mipmaps = vec![Vec::new(); mip_count as usize];
+        for _ in 0..array_size as usize * image.face_count() {
+            for (mip, mipmap) in mipmaps.iter_mut().enumerate() {
+                let (w, h, depth) = image.mip_size(mip);
+                let size = surface_size(format, w, h)? * depth as usize;
+                let surfaces = data.get(..size).ok_or(XivError::TexData)?;
+                mipmap.extend(surfaces);
+                data = &data[size..];
+            }
+        }
+        image.mipmaps = mipmaps.into_iter().map(Into::into).collect();
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn legacy_header() {
+        let img = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D | TexAttributes::TEXTURE_TYPE_CUBE,
+            TexFormat::Dxt1,
+            8,
+            4,
+            1,
+            1,
+            vec![
+                (0..6).flat_map(|face| [face; 16]).collect(),
+                (0..6).flat_map(|face| [face + 10; 8]).collect(),
+            ]
+            .into(),
+        );
+        let dds = img.to_dds().unwrap();
+        assert_eq!(&dds[..4], b"DDS ");
+        assert_eq!(u32_at(&dds, 8) & DDSD_LINEARSIZE, DDSD_LINEARSIZE);
+        assert_eq!((u32_at(&dds, 12), u32_at(&dds, 16)), (4, 8));
+        assert_eq!(u32_at(&dds, 20), 16);
+        assert_eq!(u32_at(&dds, 28), 2);
+        assert_eq!(&dds[84..88], b"DXT1");
+        assert_eq!(u32_at(&dds, 112), DDSCAPS2_CUBEMAP_ALL_FACES);
+        assert_eq!(dds.len(), 128 + 6 * 24);
+        // mip levels of the first face come before the second face
+        assert_eq!(dds[128..152], [[0; 16], [10; 16]].concat()[..24]);
+        assert_eq!(dds[152], 1);
+    }
+
+    #[test]
+    fn dds_round_trip() {
+        let cube = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D | TexAttributes::TEXTURE_TYPE_CUBE,
+            TexFormat::Dxt1,
+            8,
+            4,
+            1,
+            1,
+            vec![(0..96).collect(), (0..48).collect()].into(),
+        );
+        let array = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            TexFormat::A8,
+            2,
+            1,
+            1,
+            3,
+            vec![vec![1, 2, 3, 4, 5, 6].into(), vec![7, 8, 9].into()].into(),
+        );
+        let volume = Image::new(
+            TexAttributes::TEXTURE_TYPE_3D,
+            TexFormat::Bc7,
+            4,
+            4,
+            2,
+            1,
+            vec![(0..32).collect()].into(),
+        );
+        for img in [cube, array, volume] {
+            let read = Image::from_dds(&img.to_dds().unwrap()).unwrap();
+            assert_eq!(read.attributes, img.attributes);
+            assert_eq!(read.format, img.format);
+            assert_eq!((read.width, read.height), (img.width, img.height));
+            assert_eq!((read.depth, read.array_size), (img.depth, img.array_size));
+            assert_eq!(read.mip_levels(), img.mip_levels());
+            assert_eq!(read.mip_offsets(), img.mip_offsets());
+            assert_eq!(read.mipmaps, img.mipmaps);
+        }
+
+        let mut dds = Image::from_dds(&[]).map(|_| ()).unwrap_err();
+        assert!(matches!(dds, XivError::DdsHeader(_)));
+        let mut unknown = b"DDS ".to_vec();
+        unknown.extend([0; 124]);
+        unknown[84..88].copy_from_slice(b"RXGB");
+        unknown[80] = DDPF_FOURCC as u8;
+        dds = Image::from_dds(&unknown).map(|_| ()).unwrap_err();
+        assert_eq!(dds.to_string(), "Unsupported .dds pixel format (RXGB)");
+    }
+
+    #[test]
+    fn dx10_header() {
+        let img = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            TexFormat::Bc7,
+            4,
+            4,
+            1,
+            2,
+            vec![vec![7; 32].into()].into(),
+        );
+        let dds = img.to_dds().unwrap();
+        assert_eq!(&dds[84..88], b"DX10");
+        assert_eq!(u32_at(&dds, 128), 98);
+        assert_eq!(u32_at(&dds, 132), DIMENSION_TEXTURE2D);
+        assert_eq!(u32_at(&dds, 140), 2);
+        assert_eq!(dds.len(), 148 + 32);
+
+        let img = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            TexFormat::B8G8R8A8,
+            1,
+            1,
+            1,
+            1,
+            vec![vec![1, 2, 3, 4].into()].into(),
+        );
+        let dds = img.to_dds().unwrap();
+        assert_eq!(u32_at(&dds, 80), DDPF_RGB | DDPF_ALPHAPIXELS);
+        assert_eq!(u32_at(&dds, 88), 32);
+        assert_eq!(u32_at(&dds, 92), 0xFF_0000);
+        assert_eq!(dds[128..], [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,332 @@
+use super::*;
+use byteorder::{WriteBytesExt, LE};
+use image::{imageops, RgbaImage};
+
+/// Block compression applied by `Image::encode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed `B8G8R8A8`
+    None,
+    /// `DXT1`, opaque or with 1-bit alpha
+    Bc1,
+    /// `DXT5`, with smooth alpha
+    Bc3,
+    /// Two channels (red and green), e.g. normal maps
+    Bc5,
+    /// Experimental: every block is encoded in mode 6 only, which is lossier
+    /// than what a full BC7 encoder picks per block
+    Bc7,
+}
+
+impl Image {
+    /// Builds a 2D texture out of an image, optionally generating every mip
+    /// level down to 1×1.
+    pub fn encode(
+        image: &image::DynamicImage,
+        compression: Compression,
+        mipmaps: bool,
+    ) -> Result<Image, XivError> {
+        let (w, h) = (image.width(), image.height());
+        let (Ok(width), Ok(height)) = (u16::try_from(w), u16::try_from(h)) else {
+            return Err(XivError::TexSize(w, h));
+        };
+        if width == 0 || height == 0 {
+            return Err(XivError::TexSize(w, h));
+        }
+
+        let mut levels = vec![image.to_rgba8()];
+        while mipmaps && levels.len() < MAX_MIP_LEVELS {
+            let last = levels.last().unwrap();
+            if last.width() == 1 && last.height() == 1 {
+                break;
+            }
+            let (w, h) = ((last.width() / 2).max(1), (last.height() / 2).max(1));
+            levels.push(imageops::resize(last, w, h, imageops::FilterType::Triangle));
+        }
+
+        let format = match compression {
+            Compression::None => TexFormat::B8G8R8A8,
+            Compression::Bc1 => TexFormat::Dxt1,
+            Compression::Bc3 => TexFormat::Dxt5,
+            Compression::Bc5 => TexFormat::Bc5,
+            Compression::Bc7 => TexFormat::Bc7,
+        };
+        let mipmaps = levels
+            .iter()
+            .map(|level| encode_surface(level, compression))
+            .collect();
+
+        Ok(Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            format,
+            width,
+            height,
+            1,
+            1,
+            mipmaps,
+        ))
+    }
+
+    /// Writes the image into a .tex file as stored within SqPack repository.
+    /// Mip level count and offsets are taken from the data rather than the
+    /// header fields.
+    pub fn to_tex(&self) -> Result<Vec<u8>, XivError> {
+        if self.mipmaps.is_empty() || self.mipmaps.len() > MAX_MIP_LEVELS {
+            return Err(XivError::TexData);
+        }
+
+        let mut out = Vec::new();
+        // writing into Vec never fails
+        out.write_u32::<LE>(self.attributes.0).unwrap();
+        out.write_u32::<LE>(self.format.into()).unwrap();
+        out.write_u16::<LE>(self.width).unwrap();
+        out.write_u16::<LE>(self.height).unwrap();
+        out.write_u16::<LE>(self.depth).unwrap();
+        out.write_u8(self.mipmaps.len() as u8 | (self.mip_flag as u8) << 7)
+            .unwrap();
+        out.write_u8(self.array_size).unwrap();
+        for offset in self.lod_offsets() {
+            out.write_u32::<LE>(offset).unwrap();
+        }
+        for offset in self.mip_offsets() {
+            out.write_u32::<LE>(offset).unwrap();
+        }
+        for data in self.mipmaps.iter() {
+            out.extend_from_slice(data);
+        }
+        Ok(out)
+    }
+}
+
+fn encode_surface(image: &RgbaImage, compression: Compression) -> ImageData {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let texpresso = match compression {
+        Compression::None => {
+            let mut data = image.as_raw().clone();
+            data.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+            return data.into();
+        }
+        Compression::Bc1 => texpresso::Format::Bc1,
+        Compression::Bc3 => texpresso::Format::Bc3,
+        Compression::Bc5 => texpresso::Format::Bc5,
+        Compression::Bc7 => {
+            let mut data = Vec::with_capacity(w.div_ceil(4) * h.div_ceil(4) * 16);
+            for by in (0..h).step_by(4) {
+                for bx in (0..w).step_by(4) {
+                    data.extend(encode_bc7_block(&read_block(image, bx, by)));
+                }
+            }
+            return data.into();
+        }
+    };
+    let mut data = vec![0; texpresso.compressed_size(w, h)];
+    let params = texpresso::Params::default();
+    texpresso.compress(image.as_raw(), w, h, params, &mut data);
+    data.into()
+}
+
+/// Reads a 4×4 block of pixels, repeating edge pixels past the image bounds.
+fn read_block(image: &RgbaImage, x: usize, y: usize) -> [[u8; 4]; 16] {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    std::array::from_fn(|i| {
+        let px = (x + i % 4).min(w - 1) as u32;
+        let py = (y + i / 4).min(h - 1) as u32;
+        image.get_pixel(px, py).0
+    })
+}
+
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Encodes a block with BC7 mode 6: a single RGBA line with 16 steps, fitted
+/// along the principal axis of block colors.
+fn encode_bc7_block(pixels: &[[u8; 4]; 16]) -> [u8; 16] {
+    let mut mean = [0f32; 4];
+    for px in pixels {
+        for c in 0..4 {
+            mean[c] += px[c] as f32 / 16.0;
+        }
+    }
+    let mut cov = [[0f32; 4]; 4];
+    for px in pixels {
+        let d: [f32; 4] = std::array::from_fn(|c| px[c] as f32 - mean[c]);
+        for i in 0..4 {
+            for j in 0..4 {
+                cov[i][j] += d[i] * d[j];
+            }
+        }
+    }
+    // power iteration converges to the axis of the largest variance, seeded
+    // with covariance of the most varying channel so that it can't start
+    // orthogonal to that axis, e.g. for anti-correlated channels
+    let seed = (0..4)
+        .max_by(|&a, &b| cov[a][a].total_cmp(&cov[b][b]))
+        .unwrap();
+    let mut axis = normalize(cov[seed]).unwrap_or([1.0, 0.0, 0.0, 0.0]);
+    for _ in 0..8 {
+        let next = std::array::from_fn(|i| (0..4).map(|j| cov[i][j] * axis[j]).sum());
+        let Some(next) = normalize(next) else {
+            break;
+        };
+        axis = next;
+    }
+    let project = |px: &[u8; 4]| {
+        (0..4)
+            .map(|c| (px[c] as f32 - mean[c]) * axis[c])
+            .sum::<f32>()
+    };
+    let (min, max) = pixels
+        .iter()
+        .map(project)
+        .fold((0f32, 0f32), |(lo, hi), t| (lo.min(t), hi.max(t)));
+
+    let mut endpoints = [min, max].map(|t| {
+        let color: [f32; 4] = std::array::from_fn(|c| (mean[c] + t * axis[c]).clamp(0.0, 255.0));
+        quantize_bc7_endpoint(color)
+    });
+    let mut indices = [0u8; 16];
+    let unpacked = endpoints.map(|(color, p)| color.map(|v| (v << 1 | p) as u32));
+    let palette: [[u32; 4]; 16] = std::array::from_fn(|i| {
+        let w = BC7_WEIGHTS[i];
+        std::array::from_fn(|c| ((64 - w) * unpacked[0][c] + w * unpacked[1][c] + 32) >> 6)
+    });
+    for (index, px) in indices.iter_mut().zip(pixels) {
+        let error = |color: &[u32; 4]| -> u32 {
+            (0..4)
+                .map(|c| (color[c] as i32 - px[c] as i32).pow(2) as u32)
+                .sum()
+        };
+        *index = (0..16).min_by_key(|i| error(&palette[*i])).unwrap() as u8;
+    }
+    // the most significant bit of the first index is implied to be 0
+    if indices[0] >= 8 {
+        endpoints.swap(0, 1);
+        indices = indices.map(|i| 15 - i);
+    }
+
+    let mut bits = BitWriter::default();
+    bits.write(1 << 6, 7);
+    for c in 0..4 {
+        bits.write(endpoints[0].0[c] as u128, 7);
+        bits.write(endpoints[1].0[c] as u128, 7);
+    }
+    bits.write(endpoints[0].1 as u128, 1);
+    bits.write(endpoints[1].1 as u128, 1);
+    bits.write(indices[0] as u128, 3);
+    for index in &indices[1..] {
+        bits.write(*index as u128, 4);
+    }
+    bits.value.to_le_bytes()
+}
+
+/// Scales a vector to unit length, `None` for a zero one.
+fn normalize(v: [f32; 4]) -> Option<[f32; 4]> {
+    let len = v.iter().map(|v| v * v).sum::<f32>().sqrt();
+    (len >= f32::EPSILON).then(|| v.map(|v| v / len))
+}
+
+/// Finds 7-bit color and shared p-bit closest to an RGBA endpoint.
+fn quantize_bc7_endpoint(color: [f32; 4]) -> ([u8; 4], u8) {
+    let candidates = [0u8, 1].map(|p| {
+        let quantized = color.map(|v| ((v - p as f32) / 2.0).round().clamp(0.0, 127.0) as u8);
+        let error: f32 = (0..4)
+            .map(|c| ((quantized[c] << 1 | p) as f32 - color[c]).powi(2))
+            .sum();
+        (quantized, p, error)
+    });
+    let [a, b] = candidates;
+    let (quantized, p, _) = if a.2 <= b.2 { a } else { b };
+    (quantized, p)
+}
+
+/// Packs fields into a 128-bit block, least significant bits first.
+#[derive(Default)]
+struct BitWriter {
+    value: u128,
+    len: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u128, bits: u32) {
+        self.value |= value << self.len;
+        self.len += bits;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> image::DynamicImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255 - (x * 8) as u8])
+        })
+        .into()
+    }
+
+    fn max_error(a: &RgbaImage, b: &RgbaImage) -> u8 {
+        a.as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn mipmaps() {
+        let image = Image::encode(&gradient(8, 3), Compression::None, true).unwrap();
+        assert_eq!(image.mip_levels(), 4);
+        let sizes: Vec<_> = (0..4).map(|mip| image.mip_size(mip)).collect();
+        assert_eq!(sizes, [(8, 3, 1), (4, 1, 1), (2, 1, 1), (1, 1, 1)]);
+        for (mip, data) in image.mipmaps.iter().enumerate() {
+            let (w, h, _) = image.mip_size(mip);
+            assert_eq!(data.len(), surface_size(TexFormat::B8G8R8A8, w, h).unwrap());
+        }
+        assert_eq!(image.export().unwrap(), gradient(8, 3).to_rgba8().into());
+
+        let image = Image::encode(&gradient(8, 3), Compression::Bc1, false).unwrap();
+        assert_eq!((image.format, image.mipmaps.len()), (TexFormat::Dxt1, 1));
+        assert_eq!(image.mipmaps[0].len(), 16);
+    }
+
+    #[test]
+    fn bc7_blocks() {
+        let source = RgbaImage::from_fn(16, 12, |x, y| {
+            let t = ((x + y) * 9) as u8;
+            image::Rgba([t, 255 - t, t / 2, 255])
+        });
+        let image = Image::encode(&source.clone().into(), Compression::Bc7, false).unwrap();
+        assert_eq!(image.mipmaps[0].len(), 12 * 16);
+        assert!(max_error(&image.export().unwrap().into_rgba8(), &source) <= 4);
+
+        // red and green vary in opposite directions, blue stays constant
+        let opposite = RgbaImage::from_fn(8, 8, |x, y| {
+            let t = ((x + y) * 16) as u8;
+            image::Rgba([t, 255 - t, 90, 255])
+        });
+        let image = Image::encode(&opposite.clone().into(), Compression::Bc7, false).unwrap();
+        assert!(max_error(&image.export().unwrap().into_rgba8(), &opposite) <= 4);
+
+        let flat = RgbaImage::from_pixel(4, 4, image::Rgba([200, 10, 77, 255]));
+        let image = Image::encode(&flat.clone().into(), Compression::Bc7, false).unwrap();
+        assert!(max_error(&image.export().unwrap().into_rgba8(), &flat) <= 1);
+    }
+
+    #[test]
+    fn tex_header() {
+        let image = Image::encode(&gradient(4, 4), Compression::Bc3, true).unwrap();
+        let tex = image.to_tex().unwrap();
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(tex[offset..offset + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), TexAttributes::TEXTURE_TYPE_2D.0);
+        assert_eq!(u32_at(4), 13361);
+        assert_eq!(&tex[8..16], &[4, 0, 4, 0, 1, 0, 3, 1]);
+        assert_eq!(image.mip_offsets()[..4], [80, 96, 112, 0]);
+        assert_eq!([u32_at(16), u32_at(20), u32_at(24)], [0, 1, 2]);
+        assert_eq!(
+            [u32_at(28), u32_at(32), u32_at(36), u32_at(40)],
+            [80, 96, 112, 0]
+        );
+        assert_eq!(tex.len(), 128);
+    }
+}
@@ -0,0 +1,321 @@
+use super::*;
+use byteorder::{WriteBytesExt, LE};
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+const MODEL_RGBSDA: u8 = 1;
+const MODEL_BC1A: u8 = 128;
+const MODEL_BC2: u8 = 129;
+const MODEL_BC3: u8 = 130;
+const MODEL_BC4: u8 = 131;
+const MODEL_BC5: u8 = 132;
+const MODEL_BC6H: u8 = 133;
+const MODEL_BC7: u8 = 134;
+
+const CHANNEL_R: u8 = 0;
+const CHANNEL_G: u8 = 1;
+const CHANNEL_B: u8 = 2;
+const CHANNEL_DEPTH: u8 = 14;
+const CHANNEL_A: u8 = 15;
+
+const SAMPLE_FLOAT: u8 = 0x80;
+const SAMPLE_SIGNED: u8 = 0x40;
+
+const PRIMARIES_BT709: u8 = 1;
+const TRANSFER_LINEAR: u8 = 1;
+
+/// Channel stored at `offset` bits into a texel block.
+struct Sample {
+    offset: u16,
+    bits: u8,
+    channel: u8,
+    /// `SAMPLE_FLOAT` and `SAMPLE_SIGNED` flags, none for unsigned normalized.
+    flags: u8,
+}
+
+/// How a .tex format is described in KTX2: Vulkan format, size of its data
+/// type for endianness conversion, color model and samples of its data
+/// format descriptor and an optional swizzle of its channels.
+struct KtxFormat {
+    vk_format: u32,
+    type_size: u32,
+    model: u8,
+    block_len: u8,
+    samples: Vec<Sample>,
+    swizzle: Option<&'static str>,
+}
+
+fn ktx_format(format: TexFormat) -> Result<KtxFormat, XivError> {
+    use TexFormat::*;
+
+    let sample = |offset, bits, channel, flags| Sample {
+        offset,
+        bits,
+        channel,
+        flags,
+    };
+    let unorm = |channels: &[(u8, u8)]| {
+        let mut offset = 0;
+        let mut samples = Vec::new();
+        for (channel, bits) in channels {
+            samples.push(sample(offset, *bits, *channel, 0));
+            offset += *bits as u16;
+        }
+        samples
+    };
+    let float = |bits: u8, channels: &[u8]| {
+        channels
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| {
+                let flags = SAMPLE_FLOAT | SAMPLE_SIGNED;
+                sample(i as u16 * bits as u16, bits, *channel, flags)
+            })
+            .collect::<Vec<_>>()
+    };
+    let plain = |vk_format, type_size, block_len, samples| KtxFormat {
+        vk_format,
+        type_size,
+        model: MODEL_RGBSDA,
+        block_len,
+        samples,
+        swizzle: None,
+    };
+    let compressed = |vk_format, model, block_len, samples| KtxFormat {
+        vk_format,
+        type_size: 1,
+        model,
+        block_len,
+        samples,
+        swizzle: None,
+    };
+    let (r, g, b, a) = (CHANNEL_R, CHANNEL_G, CHANNEL_B, CHANNEL_A);
+
+    Ok(match format {
+        L8 => plain(9, 1, 1, unorm(&[(r, 8)])),
+        A8 => KtxFormat {
+            swizzle: Some("000r"),
+            ..plain(9, 1, 1, unorm(&[(r, 8)]))
+        },
+        A4R4G4B4 => plain(1000340000, 2, 2, unorm(&[(b, 4), (g, 4), (r, 4), (a, 4)])),
+        B5G5R5A1 => plain(8, 2, 2, unorm(&[(b, 5), (g, 5), (r, 5), (a, 1)])),
+        B8G8R8A8 => plain(44, 1, 4, unorm(&[(b, 8), (g, 8), (r, 8), (a, 8)])),
+        X8R8G8B8 => KtxFormat {
+            swizzle: Some("rgb1"),
+            ..plain(44, 1, 4, unorm(&[(b, 8), (g, 8), (r, 8), (a, 8)]))
+        },
+        R32F => plain(100, 4, 4, float(32, &[r])),
+        G16R16F => plain(83, 2, 4, float(16, &[r, g])),
+        G32R32F => plain(103, 4, 8, float(32, &[r, g])),
+        A16B16G16R16F => plain(97, 2, 8, float(16, &[r, g, b, a])),
+        A32B32G32R32F => plain(109, 4, 16, float(32, &[r, g, b, a])),
+        D16 => plain(124, 2, 2, unorm(&[(CHANNEL_DEPTH, 16)])),
+        Dxt1 => compressed(133, MODEL_BC1A, 8, vec![sample(0, 64, 1, 0)]),
+        Dxt3 => compressed(135, MODEL_BC2, 16, unorm(&[(a, 64), (0, 64)])),
+        Dxt5 => compressed(137, MODEL_BC3, 16, unorm(&[(a, 64), (0, 64)])),
+        Bc4 => compressed(139, MODEL_BC4, 8, unorm(&[(0, 64)])),
+        Bc5 => compressed(141, MODEL_BC5, 16, unorm(&[(r, 64), (g, 64)])),
+        Bc6h => compressed(143, MODEL_BC6H, 16, vec![sample(0, 128, 0, SAMPLE_FLOAT)]),
+        Bc7 => compressed(145, MODEL_BC7, 16, unorm(&[(0, 128)])),
+        Unknown(id) => return Err(XivError::TexFormat(id)),
+    })
+}
+
+/// Basic data format descriptor block, preceded by its total size.
+fn write_dfd(w: &mut Vec<u8>, format: &KtxFormat) {
+    let block_size = 24 + 16 * format.samples.len() as u16;
+    let block_dims = match format.model {
+        MODEL_RGBSDA => [0; 4],
+        _ => [3, 3, 0, 0],
+    };
+
+    w.write_u32::<LE>(4 + block_size as u32).unwrap();
+    w.write_u32::<LE>(0).unwrap(); // Khronos vendor, basic descriptor type
+    w.write_u16::<LE>(2).unwrap(); // version
+    w.write_u16::<LE>(block_size).unwrap();
+    w.extend([format.model, PRIMARIES_BT709, TRANSFER_LINEAR, 0]);
+    w.extend(block_dims);
+    w.extend([format.block_len, 0, 0, 0, 0, 0, 0, 0]);
+
+    for sample in &format.samples {
+        let (lower, upper) = match sample.flags {
+            0 if sample.bits >= 32 => (0, u32::MAX),
+            0 => (0, (1 << sample.bits) - 1),
+            SAMPLE_FLOAT => (0, 1.0f32.to_bits()),
+            _ => ((-1.0f32).to_bits(), 1.0f32.to_bits()),
+        };
+        w.write_u16::<LE>(sample.offset).unwrap();
+        w.write_u8(sample.bits - 1).unwrap();
+        w.write_u8(sample.channel | sample.flags).unwrap();
+        w.extend([0; 4]); // sample position
+        w.write_u32::<LE>(lower).unwrap();
+        w.write_u32::<LE>(upper).unwrap();
+    }
+}
+
+fn write_key_value(w: &mut Vec<u8>, key: &str, value: &str) {
+    let len = key.len() + value.len() + 2;
+    w.write_u32::<LE>(len as u32).unwrap();
+    w.extend(key.as_bytes());
+    w.push(0);
+    w.extend(value.as_bytes());
+    w.push(0);
+    w.resize(w.len().next_multiple_of(4), 0);
+}
+
+impl Image {
+    /// Writes the image into a KTX2 file, keeping its data as is, along with
+    /// every mip level, array layer and cube face.
+    pub fn to_ktx2(&self) -> Result<Vec<u8>, XivError> {
+        let format = ktx_format(self.format)?;
+        let subresources = self.subresources()?;
+        let level_count = self.mipmaps.len();
+        let (_, _, depth) = self.mip_size(0);
+
+        // .tex already stores each level's layers, faces and slices in the
+        // same order as KTX2 does
+        let mut levels = vec![0..0; level_count];
+        for s in &subresources {
+            let level = &mut levels[s.mip];
+            level.end = s.offset + s.data.len();
+        }
+
+        let mut kvd = Vec::new();
+        write_key_value(
+            &mut kvd,
+            "KTXwriter",
+            concat!("xiv ", env!("CARGO_PKG_VERSION")),
+        );
+        if let Some(swizzle) = format.swizzle {
+            write_key_value(&mut kvd, "KTXswizzle", swizzle);
+        }
+        let mut dfd = Vec::new();
+        write_dfd(&mut dfd, &format);
+
+        let dfd_offset = IDENTIFIER.len() + 9 * 4 + 4 * 4 + 2 * 8 + level_count * 3 * 8;
+        let kvd_offset = dfd_offset + dfd.len();
+        let align = match format.block_len {
+            1 | 2 => 4,
+            len => len as usize,
+        };
+
+        // smallest levels go first, as recommended for streaming
+        let mut level_offsets = vec![0; level_count];
+        let mut offset = kvd_offset + kvd.len();
+        for (mip, level) in levels.iter().enumerate().rev() {
+            offset = offset.next_multiple_of(align);
+            level_offsets[mip] = offset;
+            offset += level.len();
+        }
+
+        let mut out = IDENTIFIER.to_vec();
+        // writing into Vec never fails
+        let w = &mut out;
+        w.write_u32::<LE>(format.vk_format).unwrap();
+        w.write_u32::<LE>(format.type_size).unwrap();
+        w.write_u32::<LE>(self.width as u32).unwrap();
+        w.write_u32::<LE>(self.height as u32).unwrap();
+        w.write_u32::<LE>(if self.is_3d() { depth as u32 } else { 0 })
+            .unwrap();
+        let layer_count = match self.layer_count() {
+            1 => 0,
+            n => n as u32,
+        };
+        w.write_u32::<LE>(layer_count).unwrap();
+        w.write_u32::<LE>(self.face_count() as u32).unwrap();
+        w.write_u32::<LE>(level_count as u32).unwrap();
+        w.write_u32::<LE>(0).unwrap(); // no supercompression
+
+        w.write_u32::<LE>(dfd_offset as u32).unwrap();
+        w.write_u32::<LE>(dfd.len() as u32).unwrap();
+        w.write_u32::<LE>(kvd_offset as u32).unwrap();
+        w.write_u32::<LE>(kvd.len() as u32).unwrap();
+        w.write_u64::<LE>(0).unwrap();
+        w.write_u64::<LE>(0).unwrap();
+        for (level, offset) in levels.iter().zip(&level_offsets) {
+            w.write_u64::<LE>(*offset as u64).unwrap();
+            w.write_u64::<LE>(level.len() as u64).unwrap();
+            w.write_u64::<LE>(level.len() as u64).unwrap();
+        }
+        w.extend(dfd);
+        w.extend(kvd);
+
+        for (mip, level) in levels.into_iter().enumerate().rev() {
+            w.resize(level_offsets[mip], 0);
+            w.extend(&self.mipmaps[mip][level]);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> usize {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn compressed_levels() {
+        let img = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D,
+            TexFormat::Bc7,
+            8,
+            4,
+            1,
+            1,
+            vec![vec![1; 32].into(), vec![2; 16].into()].into(),
+        );
+        let ktx = img.to_ktx2().unwrap();
+        assert_eq!(ktx[..12], IDENTIFIER);
+        assert_eq!(u32_at(&ktx, 12), 145);
+        assert_eq!((u32_at(&ktx, 20), u32_at(&ktx, 24)), (8, 4));
+        assert_eq!(u32_at(&ktx, 40), 2);
+
+        // level index starts after the header and index
+        let level0 = u64_at(&ktx, 80);
+        let level1 = u64_at(&ktx, 104);
+        assert_eq!((u64_at(&ktx, 88), u64_at(&ktx, 112)), (32, 16));
+        assert!(level1 < level0);
+        assert_eq!(level0 % 16, 0);
+        assert_eq!(ktx[level0..level0 + 32], [1; 32]);
+        assert_eq!(ktx[level1..level1 + 16], [2; 16]);
+        assert_eq!(ktx.len(), level0 + 32);
+
+        let dfd_offset = u32_at(&ktx, 48) as usize;
+        assert_eq!(u32_at(&ktx, 52), 4 + 24 + 16);
+        assert_eq!(ktx[dfd_offset + 12], MODEL_BC7);
+        assert_eq!(ktx[dfd_offset + 20], 16);
+    }
+
+    #[test]
+    fn cube_with_swizzle() {
+        let img = Image::new(
+            TexAttributes::TEXTURE_TYPE_2D | TexAttributes::TEXTURE_TYPE_CUBE,
+            TexFormat::A8,
+            2,
+            2,
+            1,
+            1,
+            vec![(0..24).collect()].into(),
+        );
+        let ktx = img.to_ktx2().unwrap();
+        assert_eq!(u32_at(&ktx, 12), 9);
+        assert_eq!(u32_at(&ktx, 32), 0, "not an array");
+        assert_eq!(u32_at(&ktx, 36), 6);
+        assert_eq!(u64_at(&ktx, 88), 24);
+        let level0 = u64_at(&ktx, 80);
+        assert_eq!(ktx[level0..], (0..24).collect::<Vec<u8>>());
+
+        let kvd_offset = u32_at(&ktx, 56) as usize;
+        let kvd = &ktx[kvd_offset..kvd_offset + u32_at(&ktx, 60) as usize];
+        assert!(kvd.windows(16).any(|w| w == b"KTXswizzle\x00000r\x00"));
+    }
+}
@@ -88,3 +88,145 @@ fn export_image() {
         assert_eq!(color, [0, 0, 0, 255], "{black} is not black");
     }
 }
+
+#[test]
+fn read_exd_all_locales() {
+    use xiv::ex::{Locale, LocalizedValue, Value};
+
+    let repo = open();
+
+    let races: Vec<xiv::ex::LocalizedRow> = xiv::ex::read_exd_localized(repo.clone(), "Race")
+        .expect("Failed to find Race.exd")
+        .map(Result::unwrap)
+        .collect();
+    let hyur = races
+        .iter()
+        .find(|r| r[1].get(Locale::English) == Some(&Value::String("Hyur".into())))
+        .expect("Race.exd should contain Hyur");
+    assert!(matches!(hyur[0], LocalizedValue::Shared(Value::UInt32(_))));
+    assert!(hyur[1].get(Locale::Japanese).is_some());
+}
+
+#[test]
+fn read_exd_sestrings() {
+    let repo = open();
+
+    let rows: Vec<xiv::ex::Row> =
+        xiv::ex::read_exd(repo.clone(), "Addon", xiv::ex::Locale::English)
+            .expect("Failed to find Addon.exd")
+            .map(Result::unwrap)
+            .collect();
+    let mut macros = 0;
+    for value in rows.iter().flatten() {
+        match value {
+            xiv::ex::Value::String(s) => assert!(!s.contains('\x02'), "{s:?} contains a payload"),
+            xiv::ex::Value::SeString(s) => {
+                assert!(!s.is_plain());
+                assert!(!s.to_plain_text().contains('\x02'));
+                macros += 1;
+            }
+            _ => {}
+        }
+    }
+    assert!(macros > 0, "Addon.exd should contain strings with macros");
+}
+
+/// Raw bytes of string columns, `None` for other columns.
+struct RawColumn(Option<Vec<u8>>);
+
+impl<'de> serde::Deserialize<'de> for RawColumn {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = RawColumn;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "exd column")
+            }
+            fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(RawColumn(None))
+            }
+            fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(RawColumn(None))
+            }
+            fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(RawColumn(None))
+            }
+            fn visit_f32<E>(self, _: f32) -> Result<Self::Value, E> {
+                Ok(RawColumn(None))
+            }
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(RawColumn(Some(v.as_bytes().to_vec())))
+            }
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(RawColumn(Some(v.to_vec())))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl serde::Serialize for RawColumn {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_none()
+    }
+}
+
+#[test]
+fn sestring_round_trip() {
+    use xiv::sestring::SeString;
+
+    let repo = open();
+
+    for sheet in ["Addon", "Item", "Quest"] {
+        let rows =
+            xiv::ex::read_exd::<Vec<RawColumn>>(repo.clone(), sheet, xiv::ex::Locale::English)
+                .unwrap_or_else(|_| panic!("Failed to find {sheet}.exd"));
+        for row in rows {
+            for raw in row.unwrap().into_iter().filter_map(|c| c.0) {
+                let s = SeString::parse(&raw).unwrap();
+                assert_eq!(s.to_bytes(), raw, "{sheet}: {s}");
+                assert_eq!(
+                    SeString::from_markup(&s.to_markup()).unwrap(),
+                    s,
+                    "{sheet}: {s}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn write_exd_byte_identical() {
+    use xiv::ex::{exd_path, exh_path, read_exd, read_exh, write, Locale, Row};
+
+    let repo = open();
+
+    for sheet in ["Race", "ModelChara", "Item"] {
+        let exh = read_exh(repo.clone(), sheet).unwrap();
+        let raw_exh = repo
+            .find(&exh_path(sheet))
+            .unwrap()
+            .unwrap()
+            .read_plain()
+            .unwrap();
+        assert_eq!(
+            write::ExhWriter::new(&exh).to_bytes(),
+            raw_exh,
+            "{sheet}.exh"
+        );
+
+        let locale = exh.resolve_locale(Locale::English).unwrap();
+        let mut writer = write::ExdWriter::new(exh);
+        for row in read_exd::<Row>(repo.clone(), sheet, locale).unwrap() {
+            writer.push(&row.unwrap()).unwrap();
+        }
+        for page in writer.pages() {
+            let path = exd_path(sheet, page.start_id, locale);
+            let raw_exd = repo.find(&path).unwrap().unwrap().read_plain().unwrap();
+            assert!(page.data == raw_exd, "{path} differs after re-encoding");
+        }
+    }
+}
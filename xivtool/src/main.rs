@@ -1,10 +1,22 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
 use clap::{Parser, Subcommand};
 use fallible_iterator::{FallibleIterator, IteratorExt};
-use std::{fs, path::Path, sync::Arc};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{collections::HashMap, fs, io, path::Path, sync::Arc};
 use xiv::{
-    ex::{read_exd, Locale, Row},
+    ex::{
+        exd_path, exh_path,
+        info::SheetInfo,
+        query::{Column, Join, Order, Query, Table},
+        read_exd_localized, read_exd_pages, read_exh,
+        row_ref::page_rows,
+        schema::SheetSchema,
+        write::{ExdWriter, ExhWriter},
+        ExVariant, Exh, Locale, LocalizedValue, Row, Value, ValueType,
+    },
+    icon::find_icon,
     sqpack::SqPack,
+    tex::{split_channels, Compression, Image, TextureKind},
 };
 
 #[derive(Parser)]
@@ -14,7 +26,7 @@ struct Cli {
     #[arg(short, long, value_name = "SQPACK_DIR")]
     repo_dir: Box<Path>,
 
-    /// Directory path to write exported files into
+    /// Directory path to write exported or imported files into
     #[arg(short, long)]
     out_dir: Option<Box<Path>>,
 
@@ -31,6 +43,64 @@ enum Commands {
     /// Export things from SqPack repository
     #[command(subcommand)]
     Export(ExportCommands),
+
+    /// Convert exported files back into game formats, written as loose files
+    #[command(subcommand)]
+    Import(ImportCommands),
+
+    /// Show metadata of things within SqPack repository
+    #[command(subcommand)]
+    Info(InfoCommands),
+
+    /// Filter, join and sort rows of a sheet
+    Query(QueryArgs),
+
+    /// Check column layouts of sheets against the ones stored in schemas
+    Check {
+        /// Directory with schemas of sheets ("<Sheet>.json")
+        #[arg(long)]
+        schema_dir: Box<Path>,
+        /// Store current layout into schemas which have none
+        #[arg(long)]
+        record: bool,
+    },
+}
+
+#[derive(clap::Args)]
+struct QueryArgs {
+    /// Sheet base name (e.g. "Item")
+    sheet: Box<str>,
+    /// Filter expression (e.g. "LevelItem > 600 and ItemUICategory = 34")
+    #[arg(short, long = "where")]
+    filter: Option<Box<str>>,
+    /// Columns to output, comma separated
+    #[arg(short, long, value_delimiter = ',')]
+    select: Vec<Box<str>>,
+    /// Column to sort by, with optional ":desc" suffix
+    #[arg(long)]
+    sort: Vec<Box<str>>,
+    /// Join sheet linked by column, as "Column" or "Column=Sheet"
+    #[arg(short, long)]
+    join: Vec<Box<str>>,
+    /// Output at most this many rows
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Locale of string columns
+    #[arg(short, long, default_value = "en")]
+    locale: Locale,
+    /// Directory with column names of sheets ("<Sheet>.json")
+    #[arg(long)]
+    schema_dir: Option<Box<Path>>,
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    format: QueryFormat,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum QueryFormat {
+    Table,
+    Csv,
+    Json,
 }
 
 #[derive(Subcommand)]
@@ -39,21 +109,152 @@ enum ListCommands {
     Exd,
 }
 
+#[derive(Subcommand)]
+enum InfoCommands {
+    /// Show header of a sheet: variant, pages, languages and columns
+    Exd {
+        /// Sheet base name (e.g. "Item")
+        sheet: Box<str>,
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: InfoFormat,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum InfoFormat {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand)]
 enum ExportCommands {
     /// Export .exd → .csv
+    ///
+    /// Strings are written as SeString markup, so `<` and `\` of plain text
+    /// are escaped with `\`.
     Exd {
         /// Export only specific file by base name (e.g. "ModelChara")
         #[arg(short, long)]
         filter: Option<Box<str>>,
+        /// Locale to export (e.g. "en", "ja", "de", "fr")
+        #[arg(short, long, default_value = "en")]
+        locale: Locale,
+        /// Export every locale of a sheet at once, one column per locale for strings
+        #[arg(long, conflicts_with = "locale")]
+        all_locales: bool,
     },
-    /// Export .tex -> .png/.jpg/.tga
+    /// Export .tex -> .png/.jpg/.tga/.dds/.ktx2
     Tex {
-        /// Target .tex file within SqPack repository
+        /// Target .tex file within SqPack repository, or a loose .tex file on disk
         path: Box<str>,
         /// Export file format
         #[arg(short, long, default_value = "png")]
         format: Box<str>,
+        /// Which mip levels, array layers, cube faces and depth slices to export
+        #[arg(long, value_enum, default_value = "first")]
+        subresources: TexSubresources,
+        /// Split the first surface into a grayscale image per channel
+        #[arg(long, value_enum, conflicts_with = "subresources")]
+        channels: Option<TexChannels>,
+    },
+    /// Export UI icons by id, or every icon referenced by a sheet column
+    Icon {
+        /// Icon id (e.g. 21001)
+        #[arg(required_unless_present = "sheet")]
+        id: Option<u32>,
+        /// Sheet base name to read icon ids from (e.g. "Item")
+        #[arg(long, conflicts_with = "id", requires = "column")]
+        sheet: Option<Box<str>>,
+        /// Column holding icon ids, by name or index (e.g. "Icon", "#10")
+        #[arg(long, requires = "sheet")]
+        column: Option<Box<str>>,
+        /// Locale of localized icons
+        #[arg(short, long, default_value = "en")]
+        locale: Locale,
+        /// Skip high resolution (_hr1) icons
+        #[arg(long)]
+        low_res: bool,
+        /// Directory with column names of sheets ("<Sheet>.json")
+        #[arg(long)]
+        schema_dir: Option<Box<Path>>,
+        /// Export file format
+        #[arg(short, long, default_value = "png")]
+        format: Box<str>,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum TexSubresources {
+    /// First surface of the largest mip level only
+    First,
+    /// Every surface into a separate file
+    Separate,
+    /// Every surface laid out into one image, a row per mip level
+    Sheet,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum TexChannels {
+    /// Channels as is, named r, g, b, a
+    Split,
+    /// Channels named after what they hold in _n, _m, _s, _d and _id
+    /// textures, with Z reconstructed for normal maps
+    Material,
+}
+
+#[derive(Subcommand)]
+enum ImportCommands {
+    /// Import .csv → .exd (in the layout written by "export exd")
+    Exd {
+        /// Source .csv file
+        path: Box<Path>,
+        /// Sheet base name (e.g. "ModelChara"), defaults to file name
+        #[arg(short, long)]
+        sheet: Option<Box<str>>,
+        /// Locale of string columns, unless there is one column per locale
+        #[arg(short, long, default_value = "en")]
+        locale: Locale,
+        /// Lay out rows into new pages of up to this many rows, rather than
+        /// into pages starting at the same ids as the original sheet has
+        #[arg(long)]
+        page_size: Option<usize>,
+    },
+    /// Import .png/.jpg/.tga/.dds → .tex
+    Tex {
+        /// Source image file, .dds and .tex files are taken as is and can't
+        /// be recompressed
+        path: Box<Path>,
+        /// Target .tex file within SqPack repository (e.g. "ui/icon/000000/000001.tex")
+        target: Box<str>,
+        /// Block compression of imported image [default: bc3]
+        #[arg(short, long, value_enum)]
+        compression: Option<TexCompression>,
+        /// Keep only the largest mip level
+        #[arg(long)]
+        no_mipmaps: bool,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum TexCompression {
+    None,
+    Bc1,
+    Bc3,
+    Bc5,
+    /// Experimental, encodes every block in a single mode
+    Bc7,
+}
+
+impl From<TexCompression> for Compression {
+    fn from(value: TexCompression) -> Self {
+        match value {
+            TexCompression::None => Compression::None,
+            TexCompression::Bc1 => Compression::Bc1,
+            TexCompression::Bc3 => Compression::Bc3,
+            TexCompression::Bc5 => Compression::Bc5,
+            TexCompression::Bc7 => Compression::Bc7,
+        }
     }
 }
 
@@ -79,22 +280,109 @@ fn list_exd(repo: Arc<SqPack>) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn export_one_exd(repo: Arc<SqPack>, out_dir: &Path, sheet_name: &str) -> anyhow::Result<()> {
-    let rows: Vec<Row> = read_exd(repo.clone(), &sheet_name, Locale::English)?
-        .transpose_into_fallible()
-        .collect()?;
+/// Type tags of row keys and columns, with a string column per locale of the
+/// sheet if `localized`.
+fn exd_csv_header(exh: &Exh, localized: bool) -> Vec<String> {
+    let mut header = vec!["u32".to_owned()];
+    if exh.variant == ExVariant::SubRows {
+        header.push("u16".to_owned());
+    }
+    for column in &exh.columns {
+        match column.vtype {
+            ValueType::String if localized => header.extend(
+                exh.languages
+                    .iter()
+                    .map(|l| format!("{}{l}", column.vtype.type_tag())),
+            ),
+            vtype => header.push(vtype.type_tag().to_owned()),
+        }
+    }
+    header
+}
+
+fn info_exd(repo: Arc<SqPack>, sheet_name: &str, format: InfoFormat) -> anyhow::Result<()> {
+    let info = SheetInfo::read(repo, sheet_name)?;
+    if let InfoFormat::Json = format {
+        serde_json::to_writer_pretty(io::stdout().lock(), &info)?;
+        println!();
+        return Ok(());
+    }
+
+    println!("{}", info.name);
+    println!("  variant: {:?}", info.variant);
+    println!("  rows: {}", info.row_count);
+    println!("  row data size: {}", info.data_offset);
+    println!("  languages: {:?}", info.languages);
+    println!("  pages:");
+    for page in &info.pages {
+        match page.end_id {
+            Some(end_id) => print!("    {}..{end_id}", page.start_id),
+            None => print!("    {}..", page.start_id),
+        }
+        println!(" ({} rows)", page.row_count);
+    }
+    println!("  columns:");
+    for column in &info.columns {
+        match column.bit {
+            Some(bit) => println!(
+                "    {:>4}  {}.{bit}@{}",
+                column.index, column.vtype, column.offset
+            ),
+            None => println!(
+                "    {:>4}  {}@{}",
+                column.index, column.vtype, column.offset
+            ),
+        }
+    }
+    let unknown = &info.unknown;
+    println!(
+        "  unknown: unk0={} unk1={} u2={} unk2={} unk3={} unk4={}",
+        unknown.unk0, unknown.unk1, unknown.u2, unknown.unk2, unknown.unk3, unknown.unk4
+    );
+    Ok(())
+}
 
+fn export_one_exd(
+    repo: Arc<SqPack>,
+    out_dir: &Path,
+    sheet_name: &str,
+    locale: Option<Locale>,
+) -> anyhow::Result<()> {
     let out_path = out_dir.join(sheet_name).with_extension("csv");
 
     fs::create_dir_all(out_path.parent().unwrap())?;
     let mut out_file = fs::File::create(&out_path)?;
-
     let mut w = csv::Writer::from_writer(&mut out_file);
-    if let Some(first_row) = rows.first() {
-        w.write_record(first_row.iter().map(|c| c.type_tag()))?;
-    }
-    for row in rows.iter() {
-        w.serialize(&row)?;
+
+    match locale {
+        Some(locale) => {
+            let exh = read_exh(repo.clone(), sheet_name)?;
+            w.write_record(exd_csv_header(&exh, false))?;
+            for page in read_exd_pages(repo.clone(), &exh, sheet_name, locale)? {
+                let page = page?;
+                for row in page_rows(&exh, &page.data, &page.location)? {
+                    let row = row?;
+                    let mut record = vec![row.id().to_string()];
+                    record.extend(row.subid().map(|subid| subid.to_string()));
+                    for value in row.values() {
+                        record.push(value?.to_markup()?);
+                    }
+                    w.write_record(&record)?;
+                }
+            }
+        }
+        None => {
+            let exh = read_exh(repo.clone(), sheet_name)?;
+            w.write_record(exd_csv_header(&exh, true))?;
+            for row in read_exd_localized(repo.clone(), sheet_name)? {
+                let row = row?;
+                w.write_record(
+                    row.iter()
+                        .flat_map(LocalizedValue::values)
+                        .map(Value::to_markup),
+                )?;
+            }
+        }
     }
     w.flush()?;
 
@@ -102,9 +390,500 @@ fn export_one_exd(repo: Arc<SqPack>, out_dir: &Path, sheet_name: &str) -> anyhow
     Ok(())
 }
 
-fn export_all_exd(repo: Arc<SqPack>, out_dir: &Path) -> anyhow::Result<()> {
+fn export_all_exd(repo: Arc<SqPack>, out_dir: &Path, locale: Option<Locale>) -> anyhow::Result<()> {
+    for sheet_name in read_root_exl(repo.clone())? {
+        if let Some(locale) = locale {
+            let exh = read_exh(repo.clone(), &sheet_name)?;
+            if exh.resolve_locale(locale).is_none() {
+                eprintln!("Skipping {sheet_name}, it has no {} locale", locale.code());
+                continue;
+            }
+        }
+        export_one_exd(repo.clone(), out_dir, &sheet_name, locale)?;
+    }
+    Ok(())
+}
+
+fn export_tex(
+    repo: Arc<SqPack>,
+    out_dir: &Path,
+    path: &str,
+    format: &str,
+    subresources: TexSubresources,
+    channels: Option<TexChannels>,
+) -> anyhow::Result<()> {
+    let local = Path::new(path);
+    let (image, out_path) = if local.is_file() {
+        let image = Image::from_reader(io::BufReader::new(fs::File::open(local)?))?;
+        (image, out_dir.join(local.file_name().unwrap()))
+    } else {
+        let path = path.to_lowercase();
+        let image = repo
+            .find(&path)?
+            .ok_or(anyhow!("{path} not found"))?
+            .read_image()?;
+        (image, out_dir.join(&path))
+    };
+    export_image(&image, path, &out_path, format, subresources, channels)
+}
+
+/// Exports an already read texture into `out_path`, with its extension
+/// replaced by `format`. `path` is the texture's own path, which tells its
+/// kind for `--channels material`.
+fn export_image(
+    image: &Image,
+    path: &str,
+    out_path: &Path,
+    format: &str,
+    subresources: TexSubresources,
+    channels: Option<TexChannels>,
+) -> anyhow::Result<()> {
+    let out_path = out_path.with_extension(format);
+    fs::create_dir_all(out_path.parent().unwrap())?;
+
+    if let Some(channels) = channels {
+        let kind = match channels {
+            TexChannels::Split => None,
+            TexChannels::Material => Some(TextureKind::from_path(path).ok_or(anyhow!(
+                "Unable to tell texture kind of {path} by its name, use --channels split"
+            ))?),
+        };
+        let stem = out_path.file_stem().unwrap().to_string_lossy();
+        for channel in split_channels(&image.export()?, kind) {
+            let name = format!("{stem}_{}", channel.name);
+            let out_path = out_path.with_file_name(name).with_extension(format);
+            channel.image.save(&out_path)?;
+            println!("{}", out_path.to_string_lossy());
+        }
+        return Ok(());
+    }
+
+    // DDS and KTX2 keep every subresource in its original compression
+    let container = match format.to_lowercase().as_str() {
+        "dds" => Some(image.to_dds()?),
+        "ktx2" => Some(image.to_ktx2()?),
+        _ => None,
+    };
+    if let Some(data) = container {
+        fs::write(&out_path, data)?;
+        println!("{}", out_path.to_string_lossy());
+        return Ok(());
+    }
+    match subresources {
+        TexSubresources::First => image.export()?.save(&out_path)?,
+        TexSubresources::Sheet => image.contact_sheet()?.save(&out_path)?,
+        TexSubresources::Separate => {
+            let stem = out_path.file_stem().unwrap().to_string_lossy();
+            for s in image.subresources()? {
+                let mut name = format!("{stem}_mip{}", s.mip);
+                if image.layer_count() > 1 {
+                    name += &format!("_layer{}", s.layer);
+                }
+                if image.is_cube() {
+                    name += &format!("_face{}", s.face);
+                }
+                if image.is_3d() {
+                    name += &format!("_slice{}", s.slice);
+                }
+                let out_path = out_path.with_file_name(name).with_extension(format);
+                s.export(image.format)?.save(&out_path)?;
+                println!("{}", out_path.to_string_lossy());
+            }
+            return Ok(());
+        }
+    }
+    println!("{}", out_path.to_string_lossy());
+    Ok(())
+}
+
+fn import_tex(
+    out_dir: &Path,
+    path: &Path,
+    target: &str,
+    compression: Option<TexCompression>,
+    mipmaps: bool,
+) -> anyhow::Result<()> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let container = matches!(extension.as_deref(), Some("dds" | "tex"));
+    if container && (compression.is_some() || !mipmaps) {
+        bail!(
+            "{} is imported as is, --compression and --no-mipmaps don't apply to it",
+            path.to_string_lossy()
+        );
+    }
+    let image = match extension.as_deref() {
+        Some("dds") => Image::from_dds(&fs::read(path)?)?,
+        Some("tex") => Image::from_reader(io::BufReader::new(fs::File::open(path)?))?,
+        _ => {
+            let compression = compression.unwrap_or(TexCompression::Bc3);
+            Image::encode(&image::open(path)?, compression.into(), mipmaps)?
+        }
+    };
+
+    let out_path = out_dir.join(target.to_lowercase());
+    fs::create_dir_all(out_path.parent().unwrap())?;
+    fs::write(&out_path, image.to_tex()?)?;
+    println!("{}", out_path.to_string_lossy());
+    Ok(())
+}
+
+/// Icon ids referenced by a sheet column, without duplicates and zeros.
+fn sheet_icon_ids(
+    repo: Arc<SqPack>,
+    sheet_name: &str,
+    column: &str,
+    locale: Locale,
+    schema_dir: Option<&Path>,
+) -> anyhow::Result<Vec<u32>> {
+    let schema = read_schema(schema_dir, sheet_name)?;
+    let table = Table::read(repo, sheet_name, locale, schema.as_ref())?;
+    let index = table
+        .column_index(column)
+        .ok_or(anyhow!("Sheet {sheet_name} has no column {column}"))?;
+
+    let mut ids: Vec<u32> = table
+        .rows
+        .iter()
+        .filter_map(|row| row[index].as_u32())
+        .filter(|id| *id != 0)
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(ids)
+}
+
+fn export_icons(
+    repo: Arc<SqPack>,
+    out_dir: &Path,
+    ids: &[u32],
+    locale: Locale,
+    hires: bool,
+    format: &str,
+) -> anyhow::Result<()> {
+    for id in ids {
+        let Some((path, file)) = find_icon(&repo, *id, locale, hires)? else {
+            if ids.len() == 1 {
+                bail!("Icon {id} not found");
+            }
+            // sheets reference some icons which are not shipped
+            eprintln!("Icon {id} not found");
+            continue;
+        };
+        export_image(
+            &file.read_image()?,
+            &path,
+            &out_dir.join(&path),
+            format,
+            TexSubresources::First,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Where a cell of imported .csv goes to.
+enum CsvSlot {
+    /// Key or column, same for every locale
+    Shared(ValueType),
+    /// String column of one locale
+    PerLocale(usize),
+}
+
+fn import_exd(
+    repo: Arc<SqPack>,
+    out_dir: &Path,
+    path: &Path,
+    sheet_name: &str,
+    locale: Locale,
+    page_size: Option<usize>,
+) -> anyhow::Result<()> {
+    let exh = read_exh(repo.clone(), sheet_name)?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path(path)?;
+    let mut records = reader.records();
+    let header = records
+        .next()
+        .ok_or(anyhow!("{} is empty", path.to_string_lossy()))??;
+
+    let mut keys = vec![ValueType::UInt32];
+    if exh.variant == ExVariant::SubRows {
+        keys.push(ValueType::UInt16);
+    }
+    let locales = if header.len() == keys.len() + exh.columns.len() {
+        let resolved = exh
+            .resolve_locale(locale)
+            .ok_or_else(|| anyhow!("Sheet {sheet_name} has no {} locale", locale.code()))?;
+        vec![resolved]
+    } else {
+        exh.languages.clone()
+    };
+
+    let mut layout: Vec<(String, CsvSlot)> = keys
+        .iter()
+        .map(|k| (k.type_tag().to_owned(), CsvSlot::Shared(*k)))
+        .collect();
+    for column in &exh.columns {
+        match column.vtype {
+            ValueType::String if locales.len() > 1 => {
+                layout.extend(locales.iter().enumerate().map(|(i, l)| {
+                    (
+                        format!("{}{l}", column.vtype.type_tag()),
+                        CsvSlot::PerLocale(i),
+                    )
+                }))
+            }
+            vtype => layout.push((vtype.type_tag().to_owned(), CsvSlot::Shared(vtype))),
+        }
+    }
+
+    if header.len() != layout.len() {
+        bail!(
+            "{} has {} columns, sheet {sheet_name} needs {}",
+            path.to_string_lossy(),
+            header.len(),
+            layout.len()
+        );
+    }
+    for (i, (tag, (expected, _))) in header.iter().zip(&layout).enumerate() {
+        if tag != expected {
+            bail!(
+                "Column {} is {tag}, sheet {sheet_name} has {expected}",
+                i + 1
+            );
+        }
+    }
+
+    let mut writers: Vec<ExdWriter> = locales
+        .iter()
+        .map(|_| ExdWriter::new(exh.clone()))
+        .collect();
+    for (line, record) in (2..).zip(records) {
+        let record = record?;
+        let mut rows: Vec<Row> = vec![Row::with_capacity(record.len()); locales.len()];
+        for (i, (cell, (_, slot))) in record.iter().zip(&layout).enumerate() {
+            let context = || format!("{}:{line}, column {}", path.to_string_lossy(), i + 1);
+            match slot {
+                CsvSlot::Shared(vtype) => {
+                    let value = Value::parse(*vtype, cell).with_context(context)?;
+                    for row in rows.iter_mut() {
+                        row.push(value.clone());
+                    }
+                }
+                CsvSlot::PerLocale(l) => {
+                    let value = Value::parse(ValueType::String, cell).with_context(context)?;
+                    rows[*l].push(value);
+                }
+            }
+        }
+        for (writer, row) in writers.iter_mut().zip(rows) {
+            writer
+                .push(&row)
+                .with_context(|| format!("{}:{line}", path.to_string_lossy()))?;
+        }
+    }
+
+    for writer in writers.iter_mut() {
+        match page_size {
+            Some(page_size) => writer.repaginate(page_size),
+            None => writer.recount_pages(),
+        }
+    }
+
+    let write_file = |game_path: &str, data: &[u8]| -> anyhow::Result<()> {
+        let out_path = out_dir.join(game_path);
+        fs::create_dir_all(out_path.parent().unwrap())?;
+        fs::write(&out_path, data)?;
+        println!("{}", out_path.to_string_lossy());
+        Ok(())
+    };
+
+    let exh = writers[0].exh();
+    write_file(&exh_path(sheet_name), &ExhWriter::new(exh).to_bytes())?;
+    for (writer, locale) in writers.iter().zip(locales) {
+        for page in writer.pages() {
+            write_file(&exd_path(sheet_name, page.start_id, locale), &page.data)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_schema(schema_dir: Option<&Path>, sheet_name: &str) -> anyhow::Result<Option<SheetSchema>> {
+    let Some(path) = schema_dir.map(|d| d.join(sheet_name).with_extension("json")) else {
+        return Ok(None);
+    };
+    if !path.exists() {
+        return Ok(None);
+    }
+    let schema = serde_json::from_slice(&fs::read(&path)?)
+        .with_context(|| format!("Malformed schema {}", path.to_string_lossy()))?;
+    Ok(Some(schema))
+}
+
+/// Row of query result as JSON object keyed by column names.
+struct JsonRow<'a>(&'a [Column], &'a Row);
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (column, value) in self.0.iter().zip(self.1) {
+            map.serialize_entry(column.name.as_ref(), value)?;
+        }
+        map.end()
+    }
+}
+
+fn write_table(table: &Table, format: QueryFormat) -> anyhow::Result<()> {
+    let out = io::stdout().lock();
+    match format {
+        QueryFormat::Table => {
+            let cells: Vec<Vec<String>> = table
+                .rows
+                .iter()
+                .map(|row| row.iter().map(ToString::to_string).collect())
+                .collect();
+            let widths: Vec<usize> = table
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    cells
+                        .iter()
+                        .map(|r| r[i].chars().count())
+                        .chain([c.name.chars().count()])
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            let mut out = io::BufWriter::new(out);
+            let mut write_line = |line: Vec<&str>| -> io::Result<()> {
+                let padded: Vec<String> = line
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .collect();
+                io::Write::write_all(&mut out, padded.join(" | ").trim_end().as_bytes())?;
+                io::Write::write_all(&mut out, b"\n")
+            };
+            write_line(table.columns.iter().map(|c| c.name.as_ref()).collect())?;
+            for row in &cells {
+                write_line(row.iter().map(String::as_str).collect())?;
+            }
+        }
+        QueryFormat::Csv => {
+            let mut w = csv::Writer::from_writer(out);
+            w.write_record(table.columns.iter().map(|c| c.name.as_ref()))?;
+            for row in &table.rows {
+                w.serialize(row)?;
+            }
+            w.flush()?;
+        }
+        QueryFormat::Json => {
+            let rows: Vec<JsonRow> = table
+                .rows
+                .iter()
+                .map(|row| JsonRow(&table.columns, row))
+                .collect();
+            serde_json::to_writer_pretty(out, &rows)?;
+            println!();
+        }
+    }
+    Ok(())
+}
+
+fn query_exd(repo: Arc<SqPack>, args: &QueryArgs) -> anyhow::Result<Table> {
+    let sheet_name = &args.sheet;
+    let locale = args.locale;
+    let schema_dir = args.schema_dir.as_deref();
+    let schema = read_schema(schema_dir, sheet_name)?;
+
+    let joins = args
+        .join
+        .iter()
+        .map(|spec| {
+            let (column, sheet) = match spec.split_once('=') {
+                Some((column, sheet)) => (column, Some(sheet)),
+                None => (spec.as_ref(), None),
+            };
+            let sheet = sheet
+                .or_else(|| schema.as_ref()?.links.get(column).map(AsRef::as_ref))
+                .ok_or(anyhow!(
+                    "Unknown sheet linked by {column}, use \"{column}=Sheet\""
+                ))?;
+            Ok(Join {
+                column: column.into(),
+                sheet: sheet.into(),
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let order_by = args
+        .sort
+        .iter()
+        .map(|spec| match spec.rsplit_once(':') {
+            Some((column, "desc")) => (column.into(), Order::Desc),
+            Some((column, "asc")) => (column.into(), Order::Asc),
+            _ => (spec.clone(), Order::Asc),
+        })
+        .collect();
+
+    let query = Query {
+        joins,
+        filter: args.filter.as_deref().map(str::parse).transpose()?,
+        select: args.select.clone(),
+        order_by,
+        limit: args.limit,
+    };
+
+    let mut join_schemas = HashMap::new();
+    for join in &query.joins {
+        join_schemas.insert(join.sheet.clone(), read_schema(schema_dir, &join.sheet)?);
+    }
+
+    let table = Table::read(repo.clone(), sheet_name, locale, schema.as_ref())?;
+    let result = query.run(table, |other| {
+        let schema = join_schemas.get(other).and_then(Option::as_ref);
+        Table::read(repo.clone(), other, locale, schema)
+    })?;
+    Ok(result)
+}
+
+fn check_exd(repo: Arc<SqPack>, schema_dir: &Path, record: bool) -> anyhow::Result<()> {
+    let mut checked = 0;
+    let mut changed = 0;
     for sheet_name in read_root_exl(repo.clone())? {
-        export_one_exd(repo.clone(), out_dir, &sheet_name)?;
+        let Some(mut schema) = read_schema(Some(schema_dir), &sheet_name)? else {
+            continue;
+        };
+        let actual = read_exh(repo.clone(), &sheet_name)?.layout();
+        match &schema.layout {
+            Some(expected) => {
+                checked += 1;
+                let diffs = expected.diff(&actual);
+                if !diffs.is_empty() {
+                    changed += 1;
+                    println!("{sheet_name}:");
+                    for diff in diffs {
+                        println!("  {diff}");
+                    }
+                }
+            }
+            None if record => {
+                schema.layout = Some(actual);
+                let path = schema_dir.join(&*sheet_name).with_extension("json");
+                fs::write(&path, serde_json::to_vec_pretty(&schema)?)?;
+                println!("{}", path.to_string_lossy());
+            }
+            None => {}
+        }
+    }
+
+    println!("{checked} sheets checked, {changed} changed");
+    if changed > 0 {
+        bail!("Column layout of {changed} sheets has changed");
     }
     Ok(())
 }
@@ -118,25 +897,95 @@ fn main() -> anyhow::Result<()> {
         Commands::List(sub) => match sub {
             ListCommands::Exd => list_exd(repo.clone()),
         },
+        Commands::Info(sub) => match sub {
+            InfoCommands::Exd { sheet, format } => info_exd(repo.clone(), &sheet, format),
+        },
         Commands::Export(sub) => {
             let out_dir = cli
                 .out_dir
                 .ok_or(anyhow!("--out-dir is required for export commands"))?;
 
             match sub {
-                ExportCommands::Exd { filter } => match filter {
-                    Some(f) => export_one_exd(repo.clone(), &out_dir, &f),
-                    None => export_all_exd(repo.clone(), &out_dir),
-                },
-                ExportCommands::Tex { path, format } => {
-                    let path = path.to_lowercase();
-                    let image = repo.find(&path)?.ok_or(anyhow!("{path} not found"))?.read_image()?;
-                    let out_path = out_dir.join(&path).with_extension(format.as_ref());
-
-                    fs::create_dir_all(out_path.parent().unwrap())?;
-                    image.export()?.save(out_path)?;
-                    Ok(())
+                ExportCommands::Exd {
+                    filter,
+                    locale,
+                    all_locales,
+                } => {
+                    let locale = (!all_locales).then_some(locale);
+                    match filter {
+                        Some(f) => export_one_exd(repo.clone(), &out_dir, &f, locale),
+                        None => export_all_exd(repo.clone(), &out_dir, locale),
+                    }
+                }
+                ExportCommands::Tex {
+                    path,
+                    format,
+                    subresources,
+                    channels,
+                } => export_tex(
+                    repo.clone(),
+                    &out_dir,
+                    &path,
+                    &format,
+                    subresources,
+                    channels,
+                ),
+                ExportCommands::Icon {
+                    id,
+                    sheet,
+                    column,
+                    locale,
+                    low_res,
+                    schema_dir,
+                    format,
+                } => {
+                    let ids = match (id, sheet, column) {
+                        (Some(id), _, _) => vec![id],
+                        (None, Some(sheet), Some(column)) => sheet_icon_ids(
+                            repo.clone(),
+                            &sheet,
+                            &column,
+                            locale,
+                            schema_dir.as_deref(),
+                        )?,
+                        _ => bail!("Either icon id or --sheet and --column are required"),
+                    };
+                    export_icons(repo.clone(), &out_dir, &ids, locale, !low_res, &format)
+                }
+            }
+        }
+        Commands::Query(args) => {
+            let table = query_exd(repo.clone(), &args)?;
+            write_table(&table, args.format)
+        }
+        Commands::Check { schema_dir, record } => check_exd(repo.clone(), &schema_dir, record),
+        Commands::Import(sub) => {
+            let out_dir = cli
+                .out_dir
+                .ok_or(anyhow!("--out-dir is required for import commands"))?;
+
+            match sub {
+                ImportCommands::Exd {
+                    path,
+                    sheet,
+                    locale,
+                    page_size,
+                } => {
+                    let sheet = match sheet {
+                        Some(sheet) => sheet,
+                        None => path
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().into())
+                            .ok_or(anyhow!("Unable to guess sheet name, use --sheet"))?,
+                    };
+                    import_exd(repo.clone(), &out_dir, &path, &sheet, locale, page_size)
                 }
+                ImportCommands::Tex {
+                    path,
+                    target,
+                    compression,
+                    no_mipmaps,
+                } => import_tex(&out_dir, &path, &target, compression, !no_mipmaps),
             }
         }
     }